@@ -0,0 +1,55 @@
+//! CSV rendering of the deco stop schedule (`--format csv`).
+
+use crate::units::{self, Units};
+use crate::{round_to, OutputPayload};
+
+/// Summary comment line followed by one `depth,duration` row per stop.
+///
+/// Values use Rust's shortest round-trip float formatting so the output is
+/// deterministic for a given input. `precision` rounds them as for JSON, and
+/// `dual_units` gives every depth in meters then feet.
+pub fn render(out: &OutputPayload, precision: Option<u32>, dual_units: bool) -> String {
+    let number = |value: f64| match precision {
+        Some(decimals) => round_to(value, decimals).to_string(),
+        None => value.to_string(),
+    };
+    let units: &[Units] = match (dual_units, out.units) {
+        (true, _) => &[Units::Metric, Units::Imperial],
+        (false, Units::Metric) => &[Units::Metric],
+        (false, Units::Imperial) => &[Units::Imperial],
+    };
+    let suffix = |units: &Units| match units {
+        Units::Metric => "m",
+        Units::Imperial => "ft",
+    };
+    let depth = |units: &Units, meters: f64| match units {
+        Units::Metric => number(meters),
+        Units::Imperial => number(units::m_to_feet(meters)),
+    };
+    let ndl = out.ndl_min.map(|n| n.to_string()).unwrap_or_default();
+
+    let ceiling: Vec<String> = units
+        .iter()
+        .map(|u| format!("ceiling_{}={}", suffix(u), depth(u, out.ceiling_m)))
+        .collect();
+    let mut csv = format!(
+        "# {},tts_min={},ndl_min={ndl},input_hash={}\n",
+        ceiling.join(","),
+        number(out.tts_min),
+        out.input_hash
+    );
+    let columns: Vec<String> = units
+        .iter()
+        .map(|u| format!("depth_{}", suffix(u)))
+        .collect();
+    csv.push_str(&format!("{},duration_min\n", columns.join(",")));
+    for stop in &out.stops {
+        let depths: Vec<String> = units.iter().map(|u| depth(u, stop.depth_m)).collect();
+        csv.push_str(&format!(
+            "{},{}\n",
+            depths.join(","),
+            number(stop.duration_min)
+        ));
+    }
+    csv
+}
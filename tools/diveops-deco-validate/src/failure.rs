@@ -0,0 +1,193 @@
+//! Failure classes with their process exit codes and stable names.
+
+/// Every way processing can fail; see `--help` for the exit code table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    Usage,
+    StdinReadFailed,
+    InputReadFailed,
+    InvalidJson,
+    NoSegments,
+    InvalidGasFractions,
+    GasFractionsExceedOne,
+    SerializationFailed,
+    InvalidLastStop,
+    InvalidSac,
+    InputNotFound,
+    InvalidRate,
+    SegmentsAndDives,
+    InvalidSurfaceInterval,
+    InvalidPo2Limit,
+    UnsupportedAlgorithm,
+    InvalidAltitude,
+    InvalidGradientFactors,
+    UnsafeGradientFactors,
+    UnsupportedSchemaVersion,
+    InvalidSegment,
+    InvalidCeilingTolerance,
+    InvalidCylinder,
+    InvalidTimestamps,
+    StrictWarnings,
+    GasFractionsDoNotSumToOne,
+    EmptyInput,
+    NoDescent,
+    InvalidTargetEnd,
+    InvalidOxygenExposure,
+    OutputWriteFailed,
+    InvalidWaterTemp,
+    InputHashMismatch,
+    InvalidYoYoDelta,
+    InvalidInitialTissues,
+    InvalidYaml,
+}
+
+impl ErrorCode {
+    /// Process exit code when not running with `--json-errors`
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCode::Usage => 1,
+            ErrorCode::StdinReadFailed | ErrorCode::InputReadFailed => 2,
+            ErrorCode::InvalidJson => 3,
+            ErrorCode::NoSegments => 4,
+            ErrorCode::InvalidGasFractions => 5,
+            ErrorCode::GasFractionsExceedOne => 6,
+            ErrorCode::SerializationFailed => 7,
+            ErrorCode::InvalidLastStop => 8,
+            ErrorCode::InvalidSac => 9,
+            ErrorCode::InputNotFound => 10,
+            ErrorCode::InvalidRate => 11,
+            ErrorCode::SegmentsAndDives => 12,
+            ErrorCode::InvalidSurfaceInterval => 13,
+            ErrorCode::InvalidPo2Limit => 15,
+            ErrorCode::UnsupportedAlgorithm => 17,
+            ErrorCode::InvalidAltitude => 18,
+            ErrorCode::InvalidGradientFactors => 19,
+            ErrorCode::UnsafeGradientFactors => 20,
+            ErrorCode::UnsupportedSchemaVersion => 21,
+            ErrorCode::InvalidSegment => 22,
+            ErrorCode::InvalidCeilingTolerance => 23,
+            ErrorCode::InvalidCylinder => 25,
+            ErrorCode::InvalidTimestamps => 27,
+            ErrorCode::StrictWarnings => 28,
+            ErrorCode::GasFractionsDoNotSumToOne => 29,
+            ErrorCode::EmptyInput => 30,
+            ErrorCode::NoDescent => 31,
+            ErrorCode::InvalidTargetEnd => 32,
+            ErrorCode::InvalidOxygenExposure => 33,
+            ErrorCode::OutputWriteFailed => 34,
+            ErrorCode::InvalidWaterTemp => 35,
+            ErrorCode::InputHashMismatch => 36,
+            ErrorCode::InvalidYoYoDelta => 37,
+            ErrorCode::InvalidInitialTissues => 38,
+            ErrorCode::InvalidYaml => 40,
+        }
+    }
+
+    /// Symbolic name reported as `error_code` in JSON errors
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCode::Usage => "usage",
+            ErrorCode::StdinReadFailed => "stdin_read_failed",
+            ErrorCode::InputReadFailed => "input_read_failed",
+            ErrorCode::InvalidJson => "invalid_json",
+            ErrorCode::NoSegments => "no_segments",
+            ErrorCode::InvalidGasFractions => "invalid_gas_fractions",
+            ErrorCode::GasFractionsExceedOne => "gas_fractions_exceed_one",
+            ErrorCode::SerializationFailed => "serialization_failed",
+            ErrorCode::InvalidLastStop => "invalid_last_stop",
+            ErrorCode::InvalidSac => "invalid_sac",
+            ErrorCode::InputNotFound => "input_not_found",
+            ErrorCode::InvalidRate => "invalid_rate",
+            ErrorCode::SegmentsAndDives => "segments_and_dives",
+            ErrorCode::InvalidSurfaceInterval => "invalid_surface_interval",
+            ErrorCode::InvalidPo2Limit => "invalid_po2_limit",
+            ErrorCode::UnsupportedAlgorithm => "unsupported_algorithm",
+            ErrorCode::InvalidAltitude => "invalid_altitude",
+            ErrorCode::InvalidGradientFactors => "invalid_gradient_factors",
+            ErrorCode::UnsafeGradientFactors => "unsafe_gradient_factors",
+            ErrorCode::UnsupportedSchemaVersion => "unsupported_schema_version",
+            ErrorCode::InvalidSegment => "invalid_segment",
+            ErrorCode::InvalidCeilingTolerance => "invalid_ceiling_tolerance",
+            ErrorCode::InvalidCylinder => "invalid_cylinder",
+            ErrorCode::InvalidTimestamps => "invalid_timestamps",
+            ErrorCode::StrictWarnings => "strict_warnings",
+            ErrorCode::GasFractionsDoNotSumToOne => "gas_fractions_do_not_sum_to_one",
+            ErrorCode::EmptyInput => "empty_input",
+            ErrorCode::NoDescent => "no_descent",
+            ErrorCode::InvalidTargetEnd => "invalid_target_end",
+            ErrorCode::InvalidOxygenExposure => "invalid_oxygen_exposure",
+            ErrorCode::OutputWriteFailed => "output_write_failed",
+            ErrorCode::InvalidWaterTemp => "invalid_water_temp",
+            ErrorCode::InputHashMismatch => "input_hash_mismatch",
+            ErrorCode::InvalidYoYoDelta => "invalid_yo_yo_delta",
+            ErrorCode::InvalidInitialTissues => "invalid_initial_tissues",
+            ErrorCode::InvalidYaml => "invalid_yaml",
+        }
+    }
+}
+
+/// A condition that stops processing
+#[derive(Debug)]
+pub struct Failure {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Further problems found alongside this one under `--all-errors`
+    pub others: Vec<Failure>,
+}
+
+impl Failure {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            others: vec![],
+        }
+    }
+}
+
+/// Validation failures collected so they can be reported together; without
+/// `collect` the first one is returned at once
+#[derive(Debug, Default)]
+pub struct Failures {
+    collect: bool,
+    found: Vec<Failure>,
+}
+
+impl Failures {
+    pub fn new(collect: bool) -> Self {
+        Self {
+            collect,
+            found: vec![],
+        }
+    }
+
+    /// Record a failure, or return it when failing fast
+    pub fn push(&mut self, failure: Failure) -> Result<(), Failure> {
+        if !self.collect {
+            return Err(failure);
+        }
+        self.found.push(failure);
+        Ok(())
+    }
+
+    /// A failure no further checks can follow, with those found before it
+    pub fn fatal(mut self, failure: Failure) -> Failure {
+        self.found.push(failure);
+        self.into_failure().expect("a failure was just recorded")
+    }
+
+    /// The first failure found, carrying the rest, if there were any
+    pub fn finish(self) -> Result<(), Failure> {
+        match self.into_failure() {
+            Some(failure) => Err(failure),
+            None => Ok(()),
+        }
+    }
+
+    fn into_failure(self) -> Option<Failure> {
+        let mut found = self.found.into_iter();
+        let mut first = found.next()?;
+        first.others = found.collect();
+        Some(first)
+    }
+}
@@ -0,0 +1,106 @@
+/// Nitrogen fraction of air, the reference for narcosis equivalents
+const AIR_N2: f64 = 0.79;
+
+/// Equivalent narcotic depth in meters for a mix breathed at `depth_m`.
+///
+/// With `o2_narcotic` both oxygen and nitrogen count as narcotic (only helium
+/// is not); otherwise nitrogen alone is compared against air.
+pub fn end_m(depth_m: f64, o2: f64, he: f64, o2_narcotic: bool) -> f64 {
+    let narcotic_fraction = if o2_narcotic {
+        1.0 - he
+    } else {
+        (1.0 - o2 - he) / AIR_N2
+    };
+    (depth_m + 10.0) * narcotic_fraction - 10.0
+}
+
+/// Equivalent air depth in meters for a nitrox mix breathed at `depth_m`
+pub fn ead_m(depth_m: f64, o2: f64) -> f64 {
+    (depth_m + 10.0) * (1.0 - o2) / AIR_N2 - 10.0
+}
+
+/// Smallest helium fraction that brings a mix with `o2` to `target_end_m`
+/// at `depth_m`, the inverse of `end_m`; 0.0 when it's already there
+pub fn min_he_for_end(depth_m: f64, o2: f64, target_end_m: f64, o2_narcotic: bool) -> f64 {
+    let allowed = (target_end_m + 10.0) / (depth_m + 10.0);
+    let he = if o2_narcotic {
+        1.0 - allowed
+    } else {
+        1.0 - o2 - AIR_N2 * allowed
+    };
+    he.max(0.0)
+}
+
+/// Gas densities at 0°C and 1 atm (g/L)
+const O2_DENSITY_G_PER_L: f64 = 1.429;
+const N2_DENSITY_G_PER_L: f64 = 1.251;
+const HE_DENSITY_G_PER_L: f64 = 0.179;
+/// Standard atmosphere in bar, the reference pressure for the densities above
+const STANDARD_ATMOSPHERE_BAR: f64 = 1.01325;
+/// 0°C in kelvin, the reference temperature for the densities above
+const REFERENCE_TEMP_K: f64 = 273.15;
+
+/// Density in g/L of a mix at an ambient pressure in bar and a temperature
+/// in °C, scaled from the reference densities as an ideal gas
+pub fn density_g_per_l(ambient_bar: f64, o2: f64, he: f64, temp_c: f64) -> f64 {
+    let n2 = 1.0 - o2 - he;
+    let surface_density =
+        o2 * O2_DENSITY_G_PER_L + n2 * N2_DENSITY_G_PER_L + he * HE_DENSITY_G_PER_L;
+    surface_density
+        * (ambient_bar / STANDARD_ATMOSPHERE_BAR)
+        * (REFERENCE_TEMP_K / (REFERENCE_TEMP_K + temp_c))
+}
+
+/// Common name of a mix for messages: air, EAN32, O2 or TMX 18/45
+pub fn label(o2: f64, he: f64) -> String {
+    let o2_pct = (o2 * 100.0).round();
+    let he_pct = (he * 100.0).round();
+    if he_pct > 0.0 {
+        format!("TMX {o2_pct}/{he_pct}")
+    } else if o2_pct == 21.0 {
+        "air".to_string()
+    } else if o2_pct == 100.0 {
+        "O2".to_string()
+    } else {
+        format!("EAN{o2_pct}")
+    }
+}
+
+/// Parse a mix name (air, O2, EAN32, TMX 18/45) into O2 and He fractions.
+///
+/// Names are case-insensitive and may contain spaces; `nitrox`/`nx` and
+/// `trimix`/`tx` are accepted as prefixes too.
+pub fn parse_name(name: &str) -> Result<(f64, f64), String> {
+    let normalized: String = name
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    let unknown =
+        || format!("unknown gas name {name:?} (expected air, o2, ean<O2%> or tmx<O2%>/<He%>)");
+    let percent = |s: &str| -> Result<f64, String> {
+        s.parse::<f64>()
+            .ok()
+            .filter(|p| (0.0..=100.0).contains(p))
+            .map(|p| p / 100.0)
+            .ok_or_else(unknown)
+    };
+
+    match normalized.as_str() {
+        "air" => return Ok((0.21, 0.0)),
+        "o2" | "oxygen" => return Ok((1.0, 0.0)),
+        _ => {}
+    }
+    for prefix in ["trimix", "tmx", "tx"] {
+        if let Some(rest) = normalized.strip_prefix(prefix) {
+            let (o2, he) = rest.split_once('/').ok_or_else(unknown)?;
+            return Ok((percent(o2)?, percent(he)?));
+        }
+    }
+    for prefix in ["nitrox", "ean", "nx"] {
+        if let Some(rest) = normalized.strip_prefix(prefix) {
+            return Ok((percent(rest)?, 0.0));
+        }
+    }
+    Err(unknown())
+}
@@ -1,57 +1,982 @@
-use dive_deco::{BuehlmannConfig, BuehlmannModel, Deco, DecoModel, DecoStageType, Gas};
+mod csv;
+mod failure;
+mod gas;
+mod nofly;
+mod oxtox;
+mod planner;
+mod tissues;
+mod units;
+mod warning;
+mod water;
+
+use dive_deco::{BuehlmannConfig, Deco, DecoStage, DecoStageType, Gas};
+use failure::{ErrorCode, Failure, Failures};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::io::{self, Read};
+use std::io::{self, BufRead, Read, Write};
+use tissues::TrackedModel;
+use units::Units;
+use warning::{Warning, WarningCode};
+use water::Water;
+
+/// Input/output schema version this tool implements
+const SCHEMA_VERSION: u32 = 1;
+
+/// NDL reported when the model's NDL is longer (unlimited is Minutes::MAX)
+const DEFAULT_NDL_CAP_MIN: u64 = 999;
+/// Longest NDL under `--ndl-realistic`: the longest no-deco time
+/// recreational tables list (205 min at 10m on the PADI RDP)
+const REALISTIC_NDL_CAP_MIN: u64 = 205;
+
+/// Sea level surface pressure (BuehlmannConfig default)
+const SEA_LEVEL_PRESSURE_MBAR: u16 = 1013;
+
+/// Altitudes the model's surface pressure range (500-1500 mbar) covers
+const ALTITUDE_RANGE_M: std::ops::RangeInclusive<f64> = -500.0..=5000.0;
+/// Altitude above which tables are poorly validated
+const ALTITUDE_WARNING_M: f64 = 4000.0;
+
+/// Water temperatures accepted, from seawater's freezing point up
+const WATER_TEMP_RANGE_C: std::ops::RangeInclusive<f64> = -2.0..=40.0;
+
+/// Last deco stop depths agencies use; others are honored with a warning
+const STANDARD_LAST_STOPS_M: [f64; 2] = [3.0, 6.0];
+/// Range of last stop depths the planner can honor
+const LAST_STOP_RANGE_M: std::ops::RangeInclusive<f64> = 3.0..=9.0;
+
+/// Deepest first segment accepted as starting from the surface
+const SURFACE_START_MAX_M: f64 = 3.0;
+
+/// Oxygen fraction of air; richer mixes without helium are nitrox
+const AIR_O2: f64 = 0.21;
+
+/// END above which a narcosis warning is emitted
+const END_WARNING_M: f64 = 30.0;
+
+/// Allowed deviation from 1.0 of a declared o2 + he + n2, for rounded mixes
+const GAS_SUM_TOLERANCE: f64 = 0.01;
+
+/// Share of the max depth below which a stop counts as a deep stop
+const DEEP_STOP_FRACTION: f64 = 0.5;
+
+/// Longest segment accepted (30 days), which keeps a whole profile's
+/// seconds well within a 32-bit `usize`
+const MAX_SEGMENT_DURATION_MIN: f64 = 30.0 * 24.0 * 60.0;
+
+/// Most decimals `--precision` accepts; f64 carries about 15-17 digits
+const MAX_PRECISION: u32 = 15;
+
+/// PO2 (bar) a gas may be switched to at, whatever `max_deco_po2` allows
+/// breathing it at
+const SWITCH_PO2_LIMIT: f64 = 1.6;
+
+/// Slack for float noise in planned switch depths
+const SWITCH_DEPTH_EPSILON_M: f64 = 1e-6;
+
+/// Minimum PO2 (bar) considered safe to breathe
+const HYPOXIC_PO2: f64 = 0.18;
+
+/// Gradient factor below which a sanity warning is emitted
+const GF_WARNING_MIN: f64 = 0.1;
+
+/// No-fly time above which the profile is flagged as aggressive
+const NO_FLY_WARNING_HOURS: f64 = 20.0;
+
+/// No-deco dives deeper than this should include a safety stop
+const SAFETY_STOP_MIN_DIVE_DEPTH_M: f64 = 10.0;
+/// Depths and minimum duration of a segment counting as a safety stop
+const SAFETY_STOP_DEPTH_RANGE_M: std::ops::RangeInclusive<f64> = 3.0..=6.0;
+const SAFETY_STOP_MIN_DURATION_MIN: f64 = 3.0;
+
+/// Total stop time below which deco counts as minimal
+const MINIMAL_DECO_MAX_STOP_MIN: f64 = 5.0;
+
+/// Divers sharing one gas supply in the minimum gas calculation
+const MIN_GAS_DIVERS: f64 = 2.0;
+
+/// CNS percentage above which a warning is emitted
+const CNS_WARNING_PERCENT: f64 = 80.0;
+
+/// JSON Schema of `OutputPayload`, printed by `--schema`; kept in step with
+/// the struct by hand
+const OUTPUT_SCHEMA: &str = include_str!("../schema/output.schema.json");
+
+const HELP: &str = r#"diveops-deco-validate: Bühlmann ZHL-16C decompression plan for a dive profile
+
+USAGE:
+    diveops-deco-validate [FLAGS] [INPUT]
+
+Reads a JSON payload from INPUT, or stdin when no path is given, and writes
+the plan as JSON to stdout.
+
+FLAGS:
+    -h, --help           Print this help
+    --version            Print the tool version
+    --schema             Print the JSON Schema of the output
+    --json-errors        Report failures as JSON on stdout and exit 0
+    --ndjson             One payload per input line, one output line each
+    --tissues            Include per-compartment tissue loading
+    --trace              Include the tissue loading after each segment
+    --pretty             Pretty-print JSON output
+    --format json|csv    Output format (csv: the deco stop schedule)
+    --strict             Exit 28 when the plan has warnings, still printing
+                         it; unsafe gradient factors and a start at depth
+                         fail outright
+    --verbose            Include per-segment PO2, the leading compartment
+                         and the surface gradient factor
+    --quiet              Omit warnings from the output
+    --legacy-warnings    Print warnings as plain strings instead of
+                         {code, message, severity, segment_index} objects
+    --validate-only      Check the input without running the deco model; the
+                         input checks match a full run's, but without the
+                         model no_safety_stop is reported for any profile
+                         lacking one, deco or not
+    --all-errors         Report every validation failure, not only the first
+                         (the first still sets the exit code)
+    --summary            Also write a one-line summary to stderr
+    --ndl-cap MINUTES    Largest NDL reported (default 999)
+    --ndl-realistic      Also cap the NDL at 205 min (the longest table NDL)
+                         and, given sac_l_per_min, cylinder_l and fill_bar,
+                         at the minutes the gas above the reserve lasts at
+                         the final depth; ndl_capped reports either
+    --all-stages         Include every deco stage (ascent, gas switch, stop)
+    --force-deco         Run the deco simulation even within the NDL, where
+                         the direct ascent is otherwise used as is; stops
+                         stay empty either way, and tts_min is the direct
+                         ascent at 9 m/min rather than 0
+    --precision N        Round output floats to N decimals (default: full)
+    --dual-units         Report each depth in meters and feet (*_m and *_ft),
+                         whatever the input units
+    --solve-bottom-time  Report the longest last segment within the NDL
+    --solve-max-depth    Report the deepest last segment within the NDL
+    --output PATH        Write the result to PATH (parent directories are
+                         created) instead of stdout
+    --expect-hash HASH   Fail unless the input's input_hash is HASH
+                         (sha256:<hex>)
+    --yaml               Read the payload as YAML; the output stays JSON and
+                         input_hash covers the YAML as given
+
+INPUT:
+    {
+      "segments": [{"depth_m": 30, "duration_min": 20, "gas": "ean32"}],
+      "gas": {"o2": 0.21, "he": 0.0},
+      "gf_low": 0.3,
+      "gf_high": 0.7
+    }
+
+    Gases are {"o2", "he"} fractions or names (air, o2, ean32, tmx18/45).
+    An optional "n2" is checked against the other two: the three must sum
+    to 1.0 within 0.01. The echoed gas always reports n2.
+    Segments may give a start timestamp_s instead of duration_min (the last
+    segment still needs duration_min), and an ambient pressure_bar instead
+    of depth_m.
+    Optional fields:
+      schema_version, dives (instead of segments, each with segments and
+      surface_interval_min), deco_gases, algorithm (buehlmann|vpm-b),
+      conservatism (vpm-b level, or conservative|medium|aggressive for
+      GF 30/70, 40/85, 50/90 when gf_low/gf_high are absent),
+      water (salt|fresh), altitude_m, units (metric|imperial), last_stop_m,
+      sac_l_per_min, stress_sac_l_per_min, cylinder_l, fill_bar, o2_narcotic,
+      gas_density_limit_g_per_l, recorded_profile, ceiling_tolerance_m,
+      max_ascent_rate_m_per_min (recorded ascents faster than this are
+      flagged, default 10),
+      yo_yo_delta_m (depth change counted as a reversal, default 10),
+      target_end_m, prior_cns_percent, prior_otu (CNS decays with a 90 min
+      half-time over surface intervals), water_temp_c (gas temperature for
+      the density check, default 0),
+      descent_rate_m_per_min, ascent_rate_m_per_min, max_po2, max_deco_po2,
+      rounded_stops, bottom_threshold_m, config (ascent_rate_m_per_min,
+      descent_rate_m_per_min, last_stop_m, water, altitude_m, max_po2,
+      max_deco_po2, bottom_threshold_m overriding the top-level fields),
+      compare_gf ({"gf_low", "gf_high"} planned alongside for comparison),
+      initial_tissues (16 {"n2_bar", "he_bar"} as --tissues reports them:
+      the loading to start from instead of surface saturation; anything
+      below saturation is raised to it, as after a surface interval)
+
+EXIT CODES:
+    0   success (or any failure under --json-errors)
+    1   unknown argument
+    2   failed to read input
+    3   invalid JSON
+    4   no segments
+    5   invalid gas fractions
+    6   gas fractions exceed 1.0
+    7   failed to serialize output
+    8   invalid last stop depth
+    9   invalid SAC rate
+    10  input file not found
+    11  invalid ascent or descent rate
+    12  both segments and dives given
+    13  invalid surface interval
+    15  invalid PO2 limit
+    17  unsupported algorithm
+    18  invalid altitude
+    19  invalid gradient factors
+    20  unsafe gradient factors (--strict)
+    21  unsupported schema version
+    22  invalid segment
+    23  invalid ceiling tolerance
+    25  invalid cylinder size or fill
+    27  invalid segment timestamps
+    28  plan computed but has warnings (--strict; output still printed)
+    29  declared o2, he and n2 fractions don't sum to 1.0
+    30  empty input (nothing but whitespace)
+    31  first segment starts at depth with no descent rate (--strict)
+    32  invalid target END
+    33  invalid prior oxygen exposure
+    34  failed to write output
+    35  invalid water temperature
+    36  input hash differs from --expect-hash
+    37  invalid yo-yo depth delta
+    38  invalid initial tissues
+    40  invalid YAML (--yaml)
+"#;
 
 #[derive(Debug, Deserialize)]
+#[serde(try_from = "GasSpec")]
 struct InputGas {
     o2: f64,
     he: f64,
+    /// Declared N2 fraction, only used to check the mix adds up
+    n2: Option<f64>,
+}
+
+/// A gas as written in the input: explicit fractions or a name like "ean32"
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum GasSpec {
+    Fractions {
+        o2: f64,
+        he: f64,
+        #[serde(default)]
+        n2: Option<f64>,
+    },
+    Name(String),
+}
+
+impl TryFrom<GasSpec> for InputGas {
+    type Error = String;
+
+    fn try_from(spec: GasSpec) -> Result<Self, Self::Error> {
+        match spec {
+            GasSpec::Fractions { o2, he, n2 } => Ok(InputGas { o2, he, n2 }),
+            GasSpec::Name(name) => {
+                let (o2, he) = gas::parse_name(&name)?;
+                Ok(InputGas { o2, he, n2: None })
+            }
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct InputSegment {
+    /// Unset (NaN) when the segment gives `pressure_bar` instead
+    #[serde(default = "unset_depth")]
     depth_m: f64,
+    /// Ambient pressure reading, converted to `depth_m` for the water type
+    /// and altitude before anything else sees the segment
+    #[serde(default)]
+    pressure_bar: Option<f64>,
+    /// Required unless the segments carry timestamps; the last segment
+    /// always needs it since no later timestamp bounds it
+    #[serde(default)]
     duration_min: f64,
+    /// Start of the segment in seconds (absolute or from the dive start);
+    /// consecutive timestamps set the durations of a recorded profile
+    #[serde(default)]
+    timestamp_s: Option<f64>,
+    /// Gas breathed on this segment; falls back to the top-level `gas`
+    #[serde(default)]
+    gas: Option<InputGas>,
+}
+
+/// One dive of a repetitive series
+#[derive(Debug, Deserialize)]
+struct InputDive {
+    segments: Vec<InputSegment>,
+    /// Time spent at the surface on air before this dive
+    #[serde(default)]
+    surface_interval_min: f64,
+    /// Overrides the top-level `o2_narcotic` for this dive's END checks
+    #[serde(default)]
+    o2_narcotic: Option<bool>,
+}
+
+/// Decompression algorithm requested by the caller
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Algorithm {
+    #[default]
+    Buehlmann,
+    VpmB,
+}
+
+impl Algorithm {
+    /// Model description reported in the output
+    fn model_name(self) -> &'static str {
+        match self {
+            Algorithm::Buehlmann => "Bühlmann ZHL-16C",
+            Algorithm::VpmB => "VPM-B",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+enum Conservatism {
+    /// VPM-B's +0 to +5 scale
+    Level(u8),
+    Preset(GfPreset),
+}
+
+/// Gradient factor pairs for divers who'd rather not pick their own
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum GfPreset {
+    Conservative,
+    Medium,
+    Aggressive,
+}
+
+impl GfPreset {
+    /// (gf_low, gf_high) as fractions
+    fn gradient_factors(self) -> (f64, f64) {
+        match self {
+            GfPreset::Conservative => (0.3, 0.7),
+            GfPreset::Medium => (0.4, 0.85),
+            GfPreset::Aggressive => (0.5, 0.9),
+        }
+    }
+}
+
+/// Just the schema version, checked before the rest of the payload so a
+/// newer schema is reported as such rather than as a parse error
+#[derive(Debug, Deserialize)]
+struct SchemaProbe {
+    /// Schema the payload was written against; defaults to the current one
+    #[serde(default)]
+    schema_version: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
 struct InputPayload {
+    #[serde(default)]
     segments: Vec<InputSegment>,
+    /// Repetitive dives in order; the last one is reported on, earlier dives
+    /// only carry residual loading into it. Replaces `segments`.
+    #[serde(default)]
+    dives: Vec<InputDive>,
     gas: InputGas,
+    /// Deco-only gases, offered to the planner in addition to segment gases
+    #[serde(default)]
+    deco_gases: Vec<InputGas>,
+    /// Gradient factors as fractions (0.3) or integer percentages (30);
+    /// either may be left out when `conservatism` names a preset
+    #[serde(default)]
+    gf_low: Option<f64>,
+    #[serde(default)]
+    gf_high: Option<f64>,
+    /// Decompression model; only Bühlmann is implemented by dive_deco
+    #[serde(default)]
+    algorithm: Algorithm,
+    /// VPM-B conservatism level (+0 to +5), or a named gradient factor
+    /// preset for Bühlmann
+    #[serde(default)]
+    conservatism: Option<Conservatism>,
+    /// Water type, which sets how depth converts to pressure
+    #[serde(default)]
+    water: Water,
+    /// Altitude of the dive site, which lowers the surface pressure
+    #[serde(default)]
+    altitude_m: Option<f64>,
+    /// Unit system of input depths; imperial reads `depth_m` as feet
+    #[serde(default)]
+    units: Units,
+    /// Depth of the final deco stop (3m or 6m)
+    #[serde(default = "default_last_stop_m")]
+    last_stop_m: f64,
+    /// Surface air consumption for gas planning (liters per minute)
+    #[serde(default)]
+    sac_l_per_min: Option<f64>,
+    /// Cylinder water volume (liters) and fill pressure (bar), whose product
+    /// is the gas supply checked against the plan
+    #[serde(default)]
+    cylinder_l: Option<f64>,
+    #[serde(default)]
+    fill_bar: Option<f64>,
+    /// Stressed SAC per diver for the minimum gas (rock bottom) reserve
+    #[serde(default)]
+    stress_sac_l_per_min: Option<f64>,
+    /// Whether oxygen counts as narcotic for END (agencies differ)
+    #[serde(default = "default_o2_narcotic")]
+    o2_narcotic: bool,
+    /// Gas density above which a warning is emitted (GUE recommends 5.2 g/L)
+    #[serde(default = "default_gas_density_limit")]
+    gas_density_limit_g_per_l: f64,
+    /// Round each stop up to a whole minute, with TTS and the schedule
+    /// following the rounded stops
+    #[serde(default)]
+    rounded_stops: bool,
+    /// Segments come from a dive computer log rather than a plan, so they
+    /// are checked against the ceiling as they are flown
+    #[serde(default)]
+    recorded_profile: bool,
+    /// Ascent rate (m/min) between recorded segments above which a warning
+    /// is raised
+    #[serde(default = "default_max_ascent_rate_m_per_min")]
+    max_ascent_rate_m_per_min: f64,
+    /// Depth (m) a recorded profile may stray above the ceiling unflagged
+    #[serde(default)]
+    ceiling_tolerance_m: f64,
+    /// Depth change (m) that counts as a reversal for yo-yo detection
+    #[serde(default = "default_yo_yo_delta_m")]
+    yo_yo_delta_m: f64,
+    /// CNS percent and OTU carried over from before the first dive given;
+    /// CNS decays over each surface interval, OTU is a daily total
+    #[serde(default)]
+    prior_cns_percent: f64,
+    #[serde(default)]
+    prior_otu: f64,
+    /// END (m) to suggest a helium fraction for on segments that exceed it
+    #[serde(default)]
+    target_end_m: Option<f64>,
+    /// Water (and so breathing gas) temperature for the density check;
+    /// 0°C, the reference of the gas densities, is the densest case
+    #[serde(default)]
+    water_temp_c: f64,
+    /// Travel rates between segments; depth changes are instantaneous if unset
+    #[serde(default)]
+    descent_rate_m_per_min: Option<f64>,
+    #[serde(default)]
+    ascent_rate_m_per_min: Option<f64>,
+    /// PO2 limit (bar) for working segments, which sets each gas' MOD
+    #[serde(default = "default_max_po2")]
+    max_po2: f64,
+    /// PO2 limit (bar) for segments breathed on a deco gas
+    #[serde(default = "default_max_deco_po2")]
+    max_deco_po2: f64,
+    /// Depth from which time counts as bottom time
+    #[serde(default = "default_bottom_threshold_m")]
+    bottom_threshold_m: f64,
+    /// Overrides for the planning parameters above
+    #[serde(default)]
+    config: Option<InputConfig>,
+    /// Second gradient factor pair planned alongside `gf_low`/`gf_high`
+    #[serde(default)]
+    compare_gf: Option<InputGfPair>,
+    /// Compartment loading to start from, as `tissue_pressures` reports it
+    #[serde(default)]
+    initial_tissues: Option<Vec<InputTissue>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InputGfPair {
     gf_low: f64,
     gf_high: f64,
 }
 
+#[derive(Debug, Deserialize)]
+struct InputTissue {
+    n2_bar: f64,
+    he_bar: f64,
+}
+
+/// Grouped planning parameters; any key set here overrides its top-level
+/// counterpart.
+///
+/// dive_deco's `BuehlmannConfig` only holds gradient factors and surface
+/// pressure: `altitude_m` sets its `surface_pressure`. The other keys are
+/// applied by this tool (`water` scales depths before they reach the model,
+/// the rest shape the profile, schedule and checks).
+#[derive(Debug, Deserialize)]
+struct InputConfig {
+    #[serde(default)]
+    ascent_rate_m_per_min: Option<f64>,
+    #[serde(default)]
+    descent_rate_m_per_min: Option<f64>,
+    #[serde(default)]
+    last_stop_m: Option<f64>,
+    #[serde(default)]
+    water: Option<Water>,
+    #[serde(default)]
+    altitude_m: Option<f64>,
+    #[serde(default)]
+    max_po2: Option<f64>,
+    #[serde(default)]
+    max_deco_po2: Option<f64>,
+    #[serde(default)]
+    bottom_threshold_m: Option<f64>,
+}
+
+impl InputConfig {
+    fn apply(self, payload: &mut InputPayload) {
+        if self.ascent_rate_m_per_min.is_some() {
+            payload.ascent_rate_m_per_min = self.ascent_rate_m_per_min;
+        }
+        if self.descent_rate_m_per_min.is_some() {
+            payload.descent_rate_m_per_min = self.descent_rate_m_per_min;
+        }
+        if let Some(last_stop_m) = self.last_stop_m {
+            payload.last_stop_m = last_stop_m;
+        }
+        if let Some(water) = self.water {
+            payload.water = water;
+        }
+        if self.altitude_m.is_some() {
+            payload.altitude_m = self.altitude_m;
+        }
+        if let Some(max_po2) = self.max_po2 {
+            payload.max_po2 = max_po2;
+        }
+        if let Some(max_deco_po2) = self.max_deco_po2 {
+            payload.max_deco_po2 = max_deco_po2;
+        }
+        if let Some(bottom_threshold_m) = self.bottom_threshold_m {
+            payload.bottom_threshold_m = bottom_threshold_m;
+        }
+    }
+}
+
+fn default_o2_narcotic() -> bool {
+    true
+}
+
+fn default_gas_density_limit() -> f64 {
+    5.2
+}
+
+fn default_max_po2() -> f64 {
+    1.4
+}
+
+fn default_max_deco_po2() -> f64 {
+    1.6
+}
+
+fn unset_depth() -> f64 {
+    f64::NAN
+}
+
+fn default_bottom_threshold_m() -> f64 {
+    6.0
+}
+
+fn default_last_stop_m() -> f64 {
+    3.0
+}
+
+fn default_yo_yo_delta_m() -> f64 {
+    10.0
+}
+
+fn default_max_ascent_rate_m_per_min() -> f64 {
+    10.0
+}
+
+#[derive(Debug, Serialize)]
+struct OutputGas {
+    o2: f64,
+    he: f64,
+    /// The balance, implied by `o2` and `he`
+    n2: f64,
+}
+
 #[derive(Debug, Serialize)]
 struct OutputStop {
     depth_m: f64,
     duration_min: f64,
+    gas: OutputGas,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum OutputStageType {
+    Ascent,
+    GasSwitch,
+    DecoStop,
+}
+
+/// One stage of the model's full ascent plan, under `--all-stages`
+#[derive(Debug, Serialize)]
+struct OutputStage {
+    stage_type: OutputStageType,
+    start_depth_m: f64,
+    end_depth_m: f64,
+    duration_min: f64,
+    gas: OutputGas,
+}
+
+/// The plan at the `compare_gf` pair, next to the main one
+#[derive(Debug, Serialize)]
+struct OutputGfComparison {
+    gf_low: f64,
+    gf_high: f64,
+    tts_min: f64,
+    total_stop_min: f64,
+    /// This pair's total stop time minus the main plan's
+    total_stop_delta_min: f64,
+    stops: Vec<OutputStop>,
+    schedule: Vec<OutputScheduleEntry>,
+}
+
+/// A segment given as `pressure_bar`, with the depth it was flown at
+#[derive(Debug, Serialize)]
+struct OutputPressureDepth {
+    /// Zero-based index into `segments`
+    segment_index: usize,
+    pressure_bar: f64,
+    depth_m: f64,
+}
+
+/// Deepest depth a deco gas may be switched to at the deco PO2 limit
+#[derive(Debug, Serialize)]
+struct OutputDecoGas {
+    o2: f64,
+    he: f64,
+    max_switch_depth_m: f64,
+}
+
+/// A mix's MODs at the working and deco PO2 limits, for dive slates
+#[derive(Debug, Serialize)]
+struct OutputGasMod {
+    o2: f64,
+    he: f64,
+    max_po2: f64,
+    mod_m: f64,
+    max_deco_po2: f64,
+    deco_mod_m: f64,
+}
+
+/// Gas breathed from one mix, whose cylinder it comes out of
+#[derive(Debug, Serialize)]
+struct OutputGasUsage {
+    o2: f64,
+    he: f64,
+    used_l: f64,
+}
+
+/// Rough size of the decompression obligation, for quick categorization
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum DecoSeverity {
+    None,
+    Minimal,
+    Significant,
+}
+
+/// A deco stop placed on the dive's runtime clock
+#[derive(Debug, Serialize)]
+struct OutputScheduleEntry {
+    depth_m: f64,
+    duration_min: f64,
+    /// Runtime on arriving at and leaving the stop
+    runtime_start_min: f64,
+    runtime_end_min: f64,
+}
+
+/// Inert gas pressures of one compartment at the end of the dive
+#[derive(Debug, Serialize)]
+struct OutputTissue {
+    compartment: usize,
+    n2_bar: f64,
+    he_bar: f64,
+}
+
+/// Tissue loading at the end of one input segment, for `--trace`
+#[derive(Debug, Serialize)]
+struct OutputTraceEntry {
+    segment_index: usize,
+    runtime_min: f64,
+    depth_m: f64,
+    leading_compartment: usize,
+    compartments: Vec<OutputTissue>,
 }
 
+/// The plan for one payload; `schema/output.schema.json` describes it and
+/// must change with it
 #[derive(Debug, Serialize)]
 struct OutputPayload {
     tool: &'static str,
     tool_version: &'static str,
+    schema_version: u32,
     model: &'static str,
+    units: Units,
+    water: Water,
     gf_low: f64,
     gf_high: f64,
+    /// Back gas of segments without their own `gas`
+    gas: OutputGas,
+    /// Surface pressure the model was configured with (sea level unless
+    /// `altitude_m` is set)
+    surface_pressure_bar: f64,
+    /// Density of the `water` that depths were converted with
+    water_density_kg_per_m3: f64,
 
     ceiling_m: f64,
+    /// Ceiling rounded up to the next stop depth
+    ceiling_rounded_m: f64,
     tts_min: f64,
+    /// TTS had the ascent started after the last segment at or below
+    /// `bottom_threshold_m`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tts_at_bottom_min: Option<f64>,
+    /// Time spent at deco stops, excluding travel
+    total_stop_min: f64,
+    /// The travel share of `tts_min`: `tts_min - total_stop_min`, both
+    /// rounded under `rounded_stops`
+    ascent_time_min: f64,
     ndl_min: Option<u64>,
+    /// Whether `ndl_min` was truncated to the `--ndl-cap` value, or under
+    /// `--ndl-realistic` to the table or gas limit
+    ndl_capped: bool,
     deco_required: bool,
+    deco_severity: DecoSeverity,
+    /// Deepest deco stop; null when no deco is required
+    first_stop_m: Option<f64>,
+    /// Whether any stop is deeper than half the max depth, as low GF-low
+    /// values produce
+    has_deep_stops: bool,
+    /// Deco stops, always ordered deepest to shallowest
     stops: Vec<OutputStop>,
+    /// Unrounded stops and TTS when `rounded_stops` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exact_stops: Option<Vec<OutputStop>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exact_tts_min: Option<f64>,
+    /// The stops in the order flown, with cumulative runtimes
+    schedule: Vec<OutputScheduleEntry>,
+    /// Every planned stage (ascents, gas switches, stops) under `--all-stages`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stages: Option<Vec<OutputStage>>,
+    /// Switch depth of each `deco_gases` entry the planner uses at
+    /// `max_deco_po2`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    deco_gas_switch_depths: Vec<OutputDecoGas>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gf_comparison: Option<OutputGfComparison>,
+    /// MOD of every mix available on the dive (back gas, segment gases and
+    /// `deco_gases`) at `max_po2` and `max_deco_po2`
+    gas_mods: Vec<OutputGasMod>,
 
     max_depth_m: f64,
+    /// Time at or below `bottom_threshold_m`
+    bottom_time_min: f64,
+    /// Longest last segment that needs no deco, under `--solve-bottom-time`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_ndl_bottom_time_min: Option<u64>,
+    /// Deepest the last segment could be flown for its duration without
+    /// deco, capped at its gas' MOD; 0 when even the surface needs deco.
+    /// Under `--solve-max-depth`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_ndl_depth_m: Option<f64>,
+    /// Whole dive: segments, travel between them and the deco ascent
     runtime_min: f64,
+    max_end_m: f64,
+    /// Deepest equivalent air depth of the nitrox segments
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_ead_m: Option<f64>,
+    /// Whether oxygen counted as narcotic in the END figures
+    o2_narcotic: bool,
+    max_gas_density_g_per_l: f64,
+    /// Gas temperature the densities were computed at
+    water_temp_c: f64,
+    /// Running totals over the segments and the deco ascent, including
+    /// `prior_cns_percent`, `prior_otu` and earlier dives
+    cns_percent: f64,
+    otu: f64,
+    /// CNS left before the daily limit
+    cns_remaining_percent: f64,
+    /// Surface interval before flying, capped at 24 hours
+    no_fly_hours: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gas_used_l: Option<f64>,
+    /// `gas_used_l` split by mix, in order of first use
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    gas_usage: Vec<OutputGasUsage>,
+    /// Gas for two divers to ascend from the deepest point at the stress SAC
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_gas_l: Option<f64>,
+    /// Cylinder contents left after the plan
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gas_remaining_l: Option<f64>,
     input_hash: String,
+    /// SHA-256 of the compact JSON output with this field left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_hash: Option<String>,
+
+    /// Depths resolved from segments given as `pressure_bar`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pressure_depths: Vec<OutputPressureDepth>,
+    /// PO2 (bar) of each input segment, under `--verbose`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    segment_po2: Option<Vec<f64>>,
+    /// Compartment (1-16) nearest its surface M-value at the end of the
+    /// segments, under `--verbose`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    leading_compartment: Option<usize>,
+    /// Gradient factor a direct surfacing would reach, under `--verbose`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    surface_gf: Option<f64>,
+    /// Compartment (1-16) whose ceiling ends the no-deco time if the diver
+    /// stays at the final depth, under `--verbose` when `ndl_min` is within
+    /// the cap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ndl_limiting_compartment: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tissue_pressures: Option<Vec<OutputTissue>>,
+    /// Loading after every segment of the reported dive, under `--trace`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<Vec<OutputTraceEntry>>,
+
+    /// Approximate minutes at this dive's max depth on air that would leave
+    /// the loading carried over from earlier dives, for table users; absent
+    /// when a compartment holds more than that depth could load it to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    residual_n2_time_min: Option<f64>,
+    /// CNS and OTU carried into this dive after the surface interval, with
+    /// earlier dives
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cns_at_dive_start_percent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    otu_at_dive_start: Option<f64>,
+    /// Earlier dives whose residual loading is included
+    #[serde(skip_serializing_if = "is_zero")]
+    prior_dives: usize,
+    /// Turns between ascents and descents of at least `yo_yo_delta_m`,
+    /// from the surface and back; a plain dive has one
+    depth_reversals: usize,
 
+    #[serde(skip_serializing_if = "OutputWarnings::is_empty")]
+    warnings: OutputWarnings,
+    /// Advice on changing the plan, e.g. a helium fraction for `target_end_m`
+    /// or the TTS an O2 deco gas would save
     #[serde(skip_serializing_if = "Vec::is_empty")]
-    warnings: Vec<String>,
+    suggestions: Vec<String>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     error: Option<String>,
+
+    /// Warnings were raised under `--strict`; the output is still printed
+    #[serde(skip)]
+    failed_strict: bool,
+}
+
+/// Error output emitted instead of stderr text under `--json-errors`
+#[derive(Debug, Serialize)]
+struct ErrorPayload {
+    tool: &'static str,
+    tool_version: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_hash: Option<String>,
+    error: String,
+    error_code: &'static str,
+    /// Every failure, the first included, when `--all-errors` found several
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<ErrorEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorEntry {
+    error: String,
+    error_code: &'static str,
+}
+
+#[derive(Debug, Default, PartialEq)]
+enum Format {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    /// Report failures as a JSON object on stdout (exit 0) instead of stderr
+    json_errors: bool,
+    /// Read the payload from this file instead of stdin
+    input_path: Option<String>,
+    /// Write the result to this file instead of stdout (`--output`)
+    output_path: Option<String>,
+    /// `input_hash` the input must have before it is processed
+    expect_hash: Option<String>,
+    /// Treat the input as newline-delimited payloads, one output line each
+    ndjson: bool,
+    /// Include per-compartment tissue loading in the output
+    tissues: bool,
+    /// Include the tissue loading after each segment
+    trace: bool,
+    /// Pretty-print JSON output (ignored in --ndjson mode)
+    pretty: bool,
+    /// Output format of the result (`--format json|csv`)
+    format: Format,
+    /// Turn safety warnings (e.g. MOD exceeded) into errors
+    strict: bool,
+    /// Leave warnings out of the output
+    quiet: bool,
+    /// Print warnings as plain strings, as before they had codes
+    legacy_warnings: bool,
+    /// Report every validation failure instead of only the first
+    all_errors: bool,
+    /// Report every depth in both meters and feet
+    dual_units: bool,
+    /// Only parse and validate the input; the deco model is never run
+    validate_only: bool,
+    /// Write a one-line summary of the plan to stderr
+    summary: bool,
+    /// Include per-segment detail such as `segment_po2`
+    verbose: bool,
+    /// Include every deco stage, not just the stops
+    all_stages: bool,
+    /// Search the longest no-deco duration of the last segment
+    solve_bottom_time: bool,
+    /// Search the deepest no-deco depth of the last segment
+    solve_max_depth: bool,
+    /// Decimal places output floats are rounded to (`--precision`)
+    precision: Option<u32>,
+    /// Largest NDL reported (`--ndl-cap`), 999 when unset
+    ndl_cap: Option<u64>,
+    /// Also cap the NDL at what tables and the gas supply allow
+    ndl_realistic: bool,
+    /// Run the deco simulation even for dives within the NDL
+    force_deco: bool,
+    /// The input is a YAML document rather than JSON
+    yaml: bool,
+}
+
+/// Warnings as printed: structured, or plain messages under
+/// `--legacy-warnings`
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum OutputWarnings {
+    Structured(Vec<Warning>),
+    Legacy(Vec<String>),
+}
+
+impl OutputWarnings {
+    /// The warnings to print; none under `--quiet`
+    fn new(warnings: Vec<Warning>, options: &Options) -> Self {
+        let warnings = if options.quiet { vec![] } else { warnings };
+        if options.legacy_warnings {
+            OutputWarnings::Legacy(warnings.into_iter().map(|w| w.message).collect())
+        } else {
+            OutputWarnings::Structured(warnings)
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            OutputWarnings::Structured(warnings) => warnings.len(),
+            OutputWarnings::Legacy(warnings) => warnings.len(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
 }
 
 fn sha256_hex(s: &str) -> String {
@@ -61,129 +986,2233 @@ fn sha256_hex(s: &str) -> String {
     format!("sha256:{}", hex::encode(digest))
 }
 
-fn main() {
-    // --version support
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() == 2 && args[1] == "--version" {
-        println!("0.1.0");
-        return;
-    }
-
-    // Read stdin JSON
-    let mut input_json = String::new();
-    if io::stdin().read_to_string(&mut input_json).is_err() {
-        eprintln!("failed to read stdin");
-        std::process::exit(2);
-    }
+/// Ambient pressure in bar at a depth in meters of sea water
+fn ambient_pressure_bar(surface_pressure_mbar: u16, depth_m: f64) -> f64 {
+    surface_pressure_mbar as f64 / 1000.0 + depth_m / 10.0
+}
 
-    let input_hash = sha256_hex(&input_json);
+/// Standard atmosphere (barometric formula) surface pressure at an altitude
+fn altitude_pressure_mbar(altitude_m: f64) -> u16 {
+    (1013.25 * (1.0 - 2.25577e-5 * altitude_m).powf(5.25588)).round() as u16
+}
 
-    let payload: InputPayload = match serde_json::from_str(&input_json) {
-        Ok(v) => v,
-        Err(e) => {
-            eprintln!("invalid json: {e}");
-            std::process::exit(3);
-        }
-    };
+/// Gas consumed at a depth for a duration, scaled from the surface rate
+fn gas_consumed_l(sac_l_per_min: f64, depth_m: f64, duration_min: f64) -> f64 {
+    sac_l_per_min * (depth_m / 10.0 + 1.0) * duration_min
+}
 
-    // Basic validation
-    if payload.segments.is_empty() {
-        eprintln!("no segments");
-        std::process::exit(4);
+fn segment_gas(seg: &InputSegment, default_gas: Gas) -> Gas {
+    match &seg.gas {
+        Some(g) => Gas::new(g.o2, g.he),
+        None => default_gas,
     }
-    if !(0.0..=1.0).contains(&payload.gas.o2) || !(0.0..=1.0).contains(&payload.gas.he) {
-        eprintln!("invalid gas fractions");
-        std::process::exit(5);
-    }
-    if payload.gas.o2 + payload.gas.he > 1.0 {
-        eprintln!("gas fractions exceed 1.0");
-        std::process::exit(6);
+}
+
+/// Locates segment warnings in the input array: index, depth and duration
+fn segment_label(payload: &InputPayload, i: usize, seg: &InputSegment) -> String {
+    format!(
+        "segments[{i}] ({}, {} min)",
+        units::depth_label(payload.units, payload.water.actual_depth(seg.depth_m)),
+        seg.duration_min
+    )
+}
+
+/// Warning for a switch to `gas` at `depth_m` (seawater) deeper than its MOD
+/// at `SWITCH_PO2_LIMIT`; `at` prefixes the message
+fn deep_switch_warning(
+    payload: &InputPayload,
+    at: &str,
+    gas: Gas,
+    depth_m: f64,
+) -> Option<Warning> {
+    let switch_mod = gas.max_operating_depth(SWITCH_PO2_LIMIT);
+    if depth_m <= switch_mod + SWITCH_DEPTH_EPSILON_M {
+        return None;
     }
+    let fractions = gas.gas_pressures_compound(1.0);
+    let message = format!(
+        "{at}gas switch to {} at {} is deeper than its {} MOD at PO2 {SWITCH_PO2_LIMIT}",
+        gas::label(fractions.o2, fractions.he),
+        units::depth_label(payload.units, payload.water.actual_depth(depth_m)),
+        units::depth_label(payload.units, payload.water.actual_depth(switch_mod)),
+    );
+    Some(Warning::new(WarningCode::DeepGasSwitch, message))
+}
 
-    // Compute basic metrics
-    let max_depth_m = payload
-        .segments
+/// Check each segment of the reported dive for its gas' MOD, hypoxia, END
+/// and density, and each switch between segment gases against the switch
+/// PO2 limit; these need only the input, so `--validate-only` runs them too
+fn check_segment_gases(
+    payload: &InputPayload,
+    surface_pressure_mbar: u16,
+    warnings: &mut Vec<Warning>,
+) {
+    let depth_label = |depth_m| units::depth_label(payload.units, depth_m);
+    let default_gas = Gas::new(payload.gas.o2, payload.gas.he);
+    let deco_gases: Vec<Gas> = payload
+        .deco_gases
         .iter()
-        .map(|s| s.depth_m)
-        .fold(0.0_f64, f64::max);
+        .map(|g| Gas::new(g.o2, g.he))
+        .collect();
+    let mut previous_gas = None;
+    for (i, seg) in payload.segments.iter().enumerate() {
+        // Already reported as an invalid segment
+        if !seg.depth_m.is_finite() || seg.depth_m < 0.0 {
+            continue;
+        }
+        let gas = segment_gas(seg, default_gas);
+        let at = segment_label(payload, i, seg);
 
-    let runtime_min: f64 = payload.segments.iter().map(|s| s.duration_min).sum();
+        // A switch written into the profile, checked like a planned one
+        let switched = previous_gas.is_some_and(|previous| previous != gas);
+        previous_gas = Some(gas);
+        if switched {
+            if let Some(warning) =
+                deep_switch_warning(payload, &format!("{at}: "), gas, seg.depth_m)
+            {
+                warnings.push(warning.at_segment(i));
+            }
+        }
+        let fractions = gas.gas_pressures_compound(1.0);
+        let ambient_bar = ambient_pressure_bar(surface_pressure_mbar, seg.depth_m);
+        let po2 = gas.gas_pressures_compound(ambient_bar).o2;
 
-    // Convert gradient factors from fractions (0.0-1.0) to integers (0-100)
-    let gf_low = (payload.gf_low * 100.0).round() as u8;
-    let gf_high = (payload.gf_high * 100.0).round() as u8;
+        // MOD check against the working or, on a deco gas, the deco PO2 limit
+        let po2_limit = if deco_gases.contains(&gas) {
+            payload.max_deco_po2
+        } else {
+            payload.max_po2
+        };
+        let gas_mod = gas.max_operating_depth(po2_limit);
+        if seg.depth_m > gas_mod {
+            let message = format!(
+                "{at}: exceeds the {} MOD of {} at PO2 {po2_limit}",
+                depth_label(payload.water.actual_depth(gas_mod)),
+                gas::label(fractions.o2, fractions.he)
+            );
+            warnings.push(Warning::new(WarningCode::ModExceeded, message).at_segment(i));
+        }
 
-    // Configure Bühlmann model with gradient factors
-    let config = BuehlmannConfig::new().gradient_factors(gf_low, gf_high);
-    let mut model = BuehlmannModel::new(config);
+        // Hypoxic mixes can't be breathed shallow
+        if po2 < HYPOXIC_PO2 {
+            let message = format!(
+                "{at}: {} is hypoxic (PO2 {po2:.2} below {HYPOXIC_PO2})",
+                gas::label(fractions.o2, fractions.he)
+            );
+            warnings.push(Warning::new(WarningCode::HypoxicGas, message).at_segment(i));
+        }
 
-    // Create gas mix
-    let gas = Gas::new(payload.gas.o2, payload.gas.he);
+        let end = segment_end_m(payload, seg.depth_m, fractions.o2, fractions.he);
+        if end > END_WARNING_M {
+            let message = format!(
+                "{at}: END {} exceeds {}",
+                depth_label(end),
+                depth_label(END_WARNING_M)
+            );
+            warnings.push(Warning::new(WarningCode::EndExceeded, message).at_segment(i));
+        }
 
-    // Record each segment (step takes depth in meters, duration in seconds)
-    for seg in &payload.segments {
-        let seconds = (seg.duration_min * 60.0).round() as usize;
-        model.step(&seg.depth_m, &seconds, &gas);
+        let density = gas::density_g_per_l(
+            ambient_bar,
+            fractions.o2,
+            fractions.he,
+            payload.water_temp_c,
+        );
+        if density > payload.gas_density_limit_g_per_l {
+            let message = format!(
+                "{at}: gas density {density:.2} g/L exceeds {} g/L",
+                payload.gas_density_limit_g_per_l
+            );
+            warnings.push(Warning::new(WarningCode::GasDensityExceeded, message).at_segment(i));
+        }
     }
+}
 
-    // Get ceiling (meters) - this is the depth we cannot ascend above
-    let ceiling_m = model.ceiling();
-    let deco_required = ceiling_m > 0.0;
+/// END of a segment in the water dived in, like the depths it's compared with
+fn segment_end_m(payload: &InputPayload, depth_m: f64, o2: f64, he: f64) -> f64 {
+    payload
+        .water
+        .actual_depth(gas::end_m(depth_m, o2, he, payload.o2_narcotic))
+}
 
-    // Get NDL (no-deco limit in minutes) - only meaningful if not in deco
-    let ndl_min: Option<u64> = if !deco_required {
-        let ndl = model.ndl();
-        // NDL returns Minutes::MAX for surface/shallow, cap at 999
-        if ndl > 999 {
-            Some(999)
+/// Step the model through a segment, first travelling from the previous
+/// segment's depth (on the gas breathed there) at the configured rates
+fn step_segment(
+    model: &mut TrackedModel,
+    payload: &InputPayload,
+    previous: Option<(f64, Gas)>,
+    seg: &InputSegment,
+    gas: &Gas,
+) {
+    if let Some((previous_depth, previous_gas)) = previous {
+        let rate = if seg.depth_m > previous_depth {
+            payload.descent_rate_m_per_min
         } else {
-            Some(ndl as u64)
+            payload.ascent_rate_m_per_min
+        };
+        if let Some(rate) = rate.filter(|_| seg.depth_m != previous_depth) {
+            model.step_travel_with_rate(&seg.depth_m, &rate, &previous_gas);
         }
-    } else {
-        None
-    };
+    }
 
-    // Calculate deco schedule and TTS
-    let available_gases = vec![gas.clone()];
-    let Deco { deco_stages, tts } = model.deco(available_gases);
+    // Haldane loading at a constant depth is exact for any duration, so even
+    // multi-hour segments are a single step
+    let seconds = (seg.duration_min * 60.0).round() as usize;
+    model.step(&seg.depth_m, &seconds, gas);
+}
 
-    // TTS is in seconds, convert to minutes
-    let tts_min = tts as f64 / 60.0;
+/// Where a dive's first segment travels from: the surface, on that segment's
+/// gas (only flown when a descent rate is set)
+fn surface_start(segments: &[InputSegment], default_gas: Gas) -> Option<(f64, Gas)> {
+    segments
+        .first()
+        .map(|first| (0.0, segment_gas(first, default_gas)))
+}
 
+/// Direction changes between depth moves of at least `delta_m` over a dive
+/// that starts and ends at the surface; smaller wiggles are ignored
+fn depth_reversals(depths: impl Iterator<Item = f64>, delta_m: f64) -> usize {
+    let mut reversals = 0;
+    // Deepest (descending) or shallowest (ascending) point of the current
+    // move, which the next move is measured from
+    let mut extreme = 0.0;
+    let mut descending = None;
+    for depth in depths.chain(std::iter::once(0.0)) {
+        match descending {
+            None if depth - extreme >= delta_m => {
+                descending = Some(true);
+                extreme = depth;
+            }
+            None => {}
+            Some(true) if depth > extreme => extreme = depth,
+            Some(false) if depth < extreme => extreme = depth,
+            Some(true) if extreme - depth >= delta_m => {
+                reversals += 1;
+                descending = Some(false);
+                extreme = depth;
+            }
+            Some(false) if depth - extreme >= delta_m => {
+                reversals += 1;
+                descending = Some(true);
+                extreme = depth;
+            }
+            Some(_) => {}
+        }
+    }
+    reversals
+}
+
+/// Longest whole-minute stay at `depth_m` from `start` that still surfaces
+/// without a ceiling, bisected over repeated model runs and capped at `cap`
+fn max_ndl_bottom_time(
+    start: &TrackedModel,
+    payload: &InputPayload,
+    previous: Option<(f64, Gas)>,
+    depth_m: f64,
+    gas: &Gas,
+    cap: u64,
+) -> u64 {
+    let within_ndl = |minutes: u64| {
+        let mut model = start.clone();
+        let seg = InputSegment {
+            depth_m,
+            pressure_bar: None,
+            duration_min: minutes as f64,
+            timestamp_s: None,
+            gas: None,
+        };
+        step_segment(&mut model, payload, previous, &seg, gas);
+        model.ceiling() <= 0.0
+    };
+    if !within_ndl(0) {
+        return 0;
+    }
+    if within_ndl(cap) {
+        return cap;
+    }
+    // Invariant: `low` minutes are within the NDL, `high` minutes are not
+    let (mut low, mut high) = (0, cap);
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if within_ndl(mid) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+/// Precision (m) of the `max_ndl_depth` search
+const MAX_NDL_DEPTH_TOLERANCE_M: f64 = 0.01;
+
+/// Deepest (seawater) depth from `start` where `duration_min` still surfaces
+/// without a ceiling, bisected up to the gas' MOD at `max_po2`; 0.0 when
+/// no depth does
+fn max_ndl_depth(
+    start: &TrackedModel,
+    payload: &InputPayload,
+    previous: Option<(f64, Gas)>,
+    duration_min: f64,
+    gas: &Gas,
+) -> f64 {
+    let within_ndl = |depth_m: f64| {
+        let mut model = start.clone();
+        let seg = InputSegment {
+            depth_m,
+            pressure_bar: None,
+            duration_min,
+            timestamp_s: None,
+            gas: None,
+        };
+        step_segment(&mut model, payload, previous, &seg, gas);
+        model.ceiling() <= 0.0
+    };
+    let deepest = gas.max_operating_depth(payload.max_po2).max(0.0);
+    if !within_ndl(0.0) {
+        return 0.0;
+    }
+    if within_ndl(deepest) {
+        return deepest;
+    }
+    // Invariant: `low` is within the NDL, `high` is not
+    let (mut low, mut high) = (0.0, deepest);
+    while high - low > MAX_NDL_DEPTH_TOLERANCE_M {
+        let mid = (low + high) / 2.0;
+        if within_ndl(mid) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+/// Set each segment's duration from the next segment's timestamp, when the
+/// segments are timestamped; `label` prefixes error messages
+fn durations_from_timestamps(segments: &mut [InputSegment], label: &str) -> Result<(), Failure> {
+    let timestamps: Option<Vec<f64>> = segments.iter().map(|s| s.timestamp_s).collect();
+    let Some(timestamps) = timestamps else {
+        if segments.iter().any(|s| s.timestamp_s.is_some()) {
+            return Err(Failure::new(
+                ErrorCode::InvalidTimestamps,
+                format!("{label}segments must all have timestamp_s or none"),
+            ));
+        }
+        return Ok(());
+    };
+    for (i, pair) in timestamps.windows(2).enumerate() {
+        let (start, end) = (pair[0], pair[1]);
+        if end <= start {
+            return Err(Failure::new(
+                ErrorCode::InvalidTimestamps,
+                format!("{label}segments[{}]: timestamp_s must increase", i + 1),
+            ));
+        }
+        segments[i].duration_min = (end - start) / 60.0;
+    }
+    Ok(())
+}
+
+/// CNS percent and OTU over deco stages, each taken at its mean depth on the
+/// gas the planner chose for it
+fn deco_exposure(stages: &[DecoStage], surface_pressure_mbar: u16) -> (f64, f64) {
+    stages.iter().fold((0.0, 0.0), |(cns, otu), stage| {
+        let mean_depth = (stage.start_depth + stage.end_depth) / 2.0;
+        let o2 = stage.gas.gas_pressures_compound(1.0).o2;
+        let po2 = ambient_pressure_bar(surface_pressure_mbar, mean_depth) * o2;
+        let minutes = stage.duration as f64 / 60.0;
+        (
+            cns + oxtox::cns_percent(po2, minutes),
+            otu + oxtox::otu(po2, minutes),
+        )
+    })
+}
+
+/// Spend a surface interval at 0m on air
+fn step_surface_interval(model: &mut TrackedModel, minutes: f64) {
+    let seconds = (minutes * 60.0).round() as usize;
+    model.step(&0.0, &seconds, &Gas::air());
+}
+
+/// Duration of a deco stage in minutes; stops are rounded up to whole
+/// minutes when `rounded`, travel and switches never are
+fn stage_minutes(stage: &DecoStage, rounded: bool) -> f64 {
+    let minutes = stage.duration as f64 / 60.0;
+    if rounded && matches!(stage.stage_type, DecoStageType::DecoStop) {
+        minutes.ceil()
+    } else {
+        minutes
+    }
+}
+
+/// Deco stops of a schedule, deepest first
+fn output_stops(deco_stages: &[DecoStage], rounded: bool, water: Water) -> Vec<OutputStop> {
     // Extract deco stops (filter out ascent stages, keep only DecoStop)
-    let stops: Vec<OutputStop> = deco_stages
+    let mut stops: Vec<OutputStop> = deco_stages
         .iter()
         .filter(|stage| matches!(stage.stage_type, DecoStageType::DecoStop))
         .filter(|stage| stage.duration > 0)
         .map(|stage| OutputStop {
-            depth_m: stage.start_depth,
-            duration_min: stage.duration as f64 / 60.0,
+            depth_m: water.actual_depth(stage.start_depth),
+            duration_min: stage_minutes(stage, rounded),
+            gas: output_gas(&stage.gas),
         })
         .collect();
 
-    let out = OutputPayload {
+    // Guaranteed deepest-to-shallowest order for hashing and diffing; the
+    // sort is stable so stops at the same depth keep their schedule order
+    stops.sort_by(|a, b| b.depth_m.total_cmp(&a.depth_m));
+    stops
+}
+
+fn output_tissues(model: &TrackedModel) -> Vec<OutputTissue> {
+    model
+        .tissues
+        .compartments
+        .iter()
+        .enumerate()
+        .map(|(i, comp)| OutputTissue {
+            compartment: i + 1,
+            n2_bar: comp.n2,
+            he_bar: comp.he,
+        })
+        .collect()
+}
+
+fn output_gas(gas: &Gas) -> OutputGas {
+    // Fractions are the partial pressures at 1 bar
+    let fractions = gas.gas_pressures_compound(1.0);
+    OutputGas {
+        o2: fractions.o2,
+        he: fractions.he,
+        n2: fractions.n2,
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // --version support
+            "--version" => {
+                println!("0.1.0");
+                return;
+            }
+            "--help" | "-h" => {
+                print!("{HELP}");
+                return;
+            }
+            "--schema" => {
+                print!("{OUTPUT_SCHEMA}");
+                return;
+            }
+            "--json-errors" => options.json_errors = true,
+            "--ndjson" => options.ndjson = true,
+            "--tissues" => options.tissues = true,
+            "--trace" => options.trace = true,
+            "--pretty" => options.pretty = true,
+            "--strict" => options.strict = true,
+            "--verbose" => options.verbose = true,
+            "--quiet" => options.quiet = true,
+            "--legacy-warnings" => options.legacy_warnings = true,
+            "--all-errors" => options.all_errors = true,
+            "--dual-units" => options.dual_units = true,
+            "--validate-only" => options.validate_only = true,
+            "--summary" => options.summary = true,
+            "--all-stages" => options.all_stages = true,
+            "--solve-bottom-time" => options.solve_bottom_time = true,
+            "--solve-max-depth" => options.solve_max_depth = true,
+            "--format" => {
+                options.format = match args.next().as_deref() {
+                    Some("json") => Format::Json,
+                    Some("csv") => Format::Csv,
+                    _ => {
+                        eprintln!("--format expects json or csv");
+                        std::process::exit(ErrorCode::Usage.exit_code());
+                    }
+                }
+            }
+            "--ndl-realistic" => options.ndl_realistic = true,
+            "--force-deco" => options.force_deco = true,
+            "--yaml" => options.yaml = true,
+            "--ndl-cap" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(cap) => options.ndl_cap = Some(cap),
+                None => {
+                    eprintln!("--ndl-cap expects a whole number of minutes");
+                    std::process::exit(ErrorCode::Usage.exit_code());
+                }
+            },
+            "--output" => match args.next() {
+                Some(path) => options.output_path = Some(path),
+                None => {
+                    eprintln!("--output expects a file path");
+                    std::process::exit(ErrorCode::Usage.exit_code());
+                }
+            },
+            "--expect-hash" => match args.next() {
+                Some(hash) if hash.starts_with("sha256:") => options.expect_hash = Some(hash),
+                _ => {
+                    eprintln!("--expect-hash expects a sha256:<hex> hash");
+                    std::process::exit(ErrorCode::Usage.exit_code());
+                }
+            },
+            "--precision" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(decimals) if decimals <= MAX_PRECISION => options.precision = Some(decimals),
+                _ => {
+                    eprintln!("--precision expects a number of decimals from 0 to {MAX_PRECISION}");
+                    std::process::exit(ErrorCode::Usage.exit_code());
+                }
+            },
+            path if !path.starts_with('-') && options.input_path.is_none() => {
+                options.input_path = Some(path.to_string());
+            }
+            other => {
+                eprintln!("unknown argument: {other}");
+                std::process::exit(ErrorCode::Usage.exit_code());
+            }
+        }
+    }
+
+    if options.ndjson && options.format == Format::Csv {
+        eprintln!("--format csv is not supported with --ndjson");
+        std::process::exit(ErrorCode::Usage.exit_code());
+    }
+    if options.validate_only && options.format == Format::Csv {
+        eprintln!("--format csv is not supported with --validate-only");
+        std::process::exit(ErrorCode::Usage.exit_code());
+    }
+    if options.ndjson && options.yaml {
+        eprintln!("--yaml is not supported with --ndjson");
+        std::process::exit(ErrorCode::Usage.exit_code());
+    }
+
+    let mut input = open_input(&options);
+
+    // Line by line, so each result is written as soon as its payload is
+    // processed; --expect-hash has to see the whole input first
+    if options.ndjson && options.expect_hash.is_none() {
+        run_ndjson(input, &options);
+        return;
+    }
+
+    let mut input_json = String::new();
+    if let Err(e) = input.read_to_string(&mut input_json) {
+        fail(&options, None, read_failure(&options, e));
+    }
+
+    // Usually an upstream command in a pipe failed; say so instead of
+    // reporting a JSON syntax error at column 0
+    if input_json.trim().is_empty() {
+        fail(&options, None, empty_input_failure(&options));
+    }
+
+    // Covers the whole input, so under --ndjson every line at once
+    if let Some(expected) = &options.expect_hash {
+        let input_hash = sha256_hex(&input_json);
+        if !input_hash.eq_ignore_ascii_case(expected) {
+            fail(
+                &options,
+                Some(input_hash.clone()),
+                Failure::new(
+                    ErrorCode::InputHashMismatch,
+                    format!("input hash {input_hash} does not match the expected {expected}"),
+                ),
+            );
+        }
+    }
+
+    if options.ndjson {
+        run_ndjson(input_json.as_bytes(), &options);
+        return;
+    }
+
+    // Hashed as given, before a YAML document is turned into JSON
+    let input_hash = sha256_hex(&input_json);
+    if options.yaml {
+        match yaml_to_json(&input_json) {
+            Ok(json) => input_json = json,
+            Err(failure) => fail(&options, Some(input_hash), failure),
+        }
+    }
+
+    if options.format == Format::Csv {
+        match run(&input_json, &input_hash, &options) {
+            Ok(out) => {
+                if options.summary {
+                    eprintln!("{}", summary_line(&out));
+                }
+                write_output(
+                    &options,
+                    &csv::render(&out, options.precision, options.dual_units),
+                );
+                if out.failed_strict {
+                    std::process::exit(ErrorCode::StrictWarnings.exit_code());
+                }
+            }
+            Err(failure) => fail(&options, Some(input_hash), failure),
+        }
+        return;
+    }
+
+    match render_json(&input_json, &input_hash, &options, options.pretty) {
+        Ok((s, failed_strict)) => {
+            write_output(&options, &format!("{s}\n"));
+            if failed_strict {
+                std::process::exit(ErrorCode::StrictWarnings.exit_code());
+            }
+        }
+        Err(failure) => fail(&options, Some(input_hash), failure),
+    }
+}
+
+/// Rewrite a YAML payload as the equivalent JSON for the rest of the
+/// pipeline
+fn yaml_to_json(input_yaml: &str) -> Result<String, Failure> {
+    let value: serde_json::Value = serde_yaml_ng::from_str(input_yaml)
+        .map_err(|e| Failure::new(ErrorCode::InvalidYaml, format!("invalid yaml: {e}")))?;
+    serde_json::to_string(&value).map_err(|e| {
+        Failure::new(
+            ErrorCode::SerializationFailed,
+            format!("failed to convert yaml to json: {e}"),
+        )
+    })
+}
+
+/// Plan the same input again with the `compare_gf` pair as its gradient
+/// factors
+fn compare_gf(
+    input_json: &str,
+    pair: &InputGfPair,
+    options: &Options,
+) -> Result<OutputPayload, Failure> {
+    let mut value: serde_json::Value = serde_json::from_str(input_json)
+        .map_err(|e| Failure::new(ErrorCode::InvalidJson, format!("invalid json: {e}")))?;
+    if let Some(object) = value.as_object_mut() {
+        object.remove("compare_gf");
+        object.insert("gf_low".to_string(), pair.gf_low.into());
+        object.insert("gf_high".to_string(), pair.gf_high.into());
+    }
+    // Only the schedule is used; checks and warnings come from the main plan
+    let compare_options = Options {
+        ndl_cap: options.ndl_cap,
+        ..Options::default()
+    };
+    run(&value.to_string(), "", &compare_options)
+        .map_err(|failure| Failure::new(failure.code, format!("compare_gf: {}", failure.message)))
+}
+
+/// The input file, or stdin when none is given
+fn open_input(options: &Options) -> Box<dyn BufRead> {
+    let Some(path) = &options.input_path else {
+        return Box::new(io::stdin().lock());
+    };
+    match std::fs::File::open(path) {
+        Ok(file) => Box::new(io::BufReader::new(file)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => fail(
+            options,
+            None,
+            Failure::new(
+                ErrorCode::InputNotFound,
+                format!("input file not found: {path}"),
+            ),
+        ),
+        Err(e) => fail(options, None, read_failure(options, e)),
+    }
+}
+
+fn read_failure(options: &Options, e: io::Error) -> Failure {
+    match &options.input_path {
+        Some(path) => Failure::new(
+            ErrorCode::InputReadFailed,
+            format!("failed to read {path}: {e}"),
+        ),
+        None => Failure::new(ErrorCode::StdinReadFailed, "failed to read stdin"),
+    }
+}
+
+fn empty_input_failure(options: &Options) -> Failure {
+    let source = options.input_path.as_deref().unwrap_or("stdin");
+    Failure::new(
+        ErrorCode::EmptyInput,
+        format!("empty input: {source} contained no JSON payload"),
+    )
+}
+
+/// Process one payload per input line; each line gets its own output or
+/// error object so a bad line never stops the batch. Lines are read and
+/// their results flushed one at a time, so batches of any size stream.
+fn run_ndjson(input: impl BufRead, options: &Options) {
+    let mut out = open_output(options);
+    let mut any_failed_strict = false;
+    let mut any_payload = false;
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => fail(options, None, read_failure(options, e)),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        any_payload = true;
+        let input_hash = sha256_hex(&line);
+        let line_out = match render_json(&line, &input_hash, options, false) {
+            Ok((s, failed_strict)) => {
+                any_failed_strict |= failed_strict;
+                Ok(s)
+            }
+            Err(failure) => serde_json::to_string(&error_payload(Some(input_hash), failure)),
+        };
+        match line_out {
+            Ok(s) => {
+                if let Err(e) = writeln!(out, "{s}").and_then(|()| out.flush()) {
+                    output_failure(options, e);
+                }
+            }
+            Err(e) => {
+                eprintln!("failed to serialize output: {e}");
+                std::process::exit(ErrorCode::SerializationFailed.exit_code());
+            }
+        }
+    }
+    if !any_payload {
+        fail(options, None, empty_input_failure(options));
+    }
+    if any_failed_strict {
+        std::process::exit(ErrorCode::StrictWarnings.exit_code());
+    }
+}
+
+fn serialize_output(
+    out: &OutputPayload,
+    pretty: bool,
+    options: &Options,
+) -> Result<String, serde_json::Error> {
+    if out.units == Units::Metric && options.precision.is_none() && !options.dual_units {
+        return to_json(out, pretty);
+    }
+    let mut value = serde_json::to_value(out)?;
+    if options.dual_units {
+        units::to_dual(&mut value);
+    } else if out.units == Units::Imperial {
+        units::to_imperial(&mut value);
+    }
+    // After the unit conversion, so feet are rounded rather than meters
+    if let Some(decimals) = options.precision {
+        round_floats(&mut value, decimals);
+    }
+    to_json(&value, pretty)
+}
+
+/// `value` rounded to `decimals` places, without a negative zero
+fn round_to(value: f64, decimals: u32) -> f64 {
+    let scale = 10_f64.powi(decimals as i32);
+    (value * scale).round() / scale + 0.0
+}
+
+/// Round every non-integer number of a serialized output to `decimals` places
+fn round_floats(value: &mut serde_json::Value, decimals: u32) {
+    match value {
+        serde_json::Value::Number(n) if n.is_f64() => {
+            let rounded = n.as_f64().map(|f| round_to(f, decimals));
+            if let Some(rounded) = rounded.and_then(serde_json::Number::from_f64) {
+                *n = rounded;
+            }
+        }
+        serde_json::Value::Array(items) => {
+            items.iter_mut().for_each(|v| round_floats(v, decimals));
+        }
+        serde_json::Value::Object(map) => {
+            map.values_mut().for_each(|v| round_floats(v, decimals));
+        }
+        _ => {}
+    }
+}
+
+fn to_json<T: Serialize>(value: &T, pretty: bool) -> Result<String, serde_json::Error> {
+    if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
+
+fn serialization_failure(e: serde_json::Error) -> Failure {
+    Failure::new(
+        ErrorCode::SerializationFailed,
+        format!("failed to serialize output: {e}"),
+    )
+}
+
+fn error_payload(input_hash: Option<String>, failure: Failure) -> ErrorPayload {
+    let errors = if failure.others.is_empty() {
+        vec![]
+    } else {
+        std::iter::once(&failure)
+            .chain(&failure.others)
+            .map(|f| ErrorEntry {
+                error: f.message.clone(),
+                error_code: f.code.name(),
+            })
+            .collect()
+    };
+    ErrorPayload {
         tool: "diveops-deco-validate",
         tool_version: "0.1.0",
-        model: "Bühlmann ZHL-16C",
-        gf_low: payload.gf_low,
-        gf_high: payload.gf_high,
+        input_hash,
+        error: failure.message,
+        error_code: failure.code.name(),
+        errors,
+    }
+}
+
+/// Report a failure and exit: as JSON on stdout with `--json-errors`,
+/// otherwise as text on stderr with the failure's exit code
+fn fail(options: &Options, input_hash: Option<String>, failure: Failure) -> ! {
+    if options.json_errors {
+        if let Ok(s) = to_json(&error_payload(input_hash, failure), options.pretty) {
+            write_output(options, &format!("{s}\n"));
+            std::process::exit(0);
+        }
+        std::process::exit(ErrorCode::SerializationFailed.exit_code());
+    }
+    // The first failure sets the exit code
+    for f in std::iter::once(&failure).chain(&failure.others) {
+        eprintln!("{}", f.message);
+    }
+    std::process::exit(failure.code.exit_code());
+}
+
+/// Where results go: the `--output` file (parent directories created as
+/// needed) or stdout
+fn open_output(options: &Options) -> Box<dyn Write> {
+    let Some(path) = &options.output_path else {
+        return Box::new(io::stdout().lock());
+    };
+    let parent = std::path::Path::new(path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty());
+    let file = parent
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::File::create(path));
+    match file {
+        Ok(file) => Box::new(io::BufWriter::new(file)),
+        Err(e) => output_failure(options, e),
+    }
+}
+
+/// Write a complete result to the output and flush it
+fn write_output(options: &Options, text: &str) {
+    let mut out = open_output(options);
+    if let Err(e) = out.write_all(text.as_bytes()).and_then(|()| out.flush()) {
+        output_failure(options, e);
+    }
+}
+
+/// Exit on an output that can't be written. Always reported on stderr, since
+/// the output is what failed.
+fn output_failure(options: &Options, e: io::Error) -> ! {
+    let target = options.output_path.as_deref().unwrap_or("stdout");
+    eprintln!("failed to write output to {target}: {e}");
+    std::process::exit(ErrorCode::OutputWriteFailed.exit_code());
+}
+
+/// A parsed payload that passed validation, in meters and with the final
+/// dive's segments in `payload.segments`
+struct ValidInput {
+    payload: InputPayload,
+    prior_dives: Vec<InputDive>,
+    surface_interval_min: f64,
+    warnings: Vec<Warning>,
+    max_depth_m: f64,
+    bottom_time_min: f64,
+    gf_low: u8,
+    gf_high: u8,
+    /// The same gradient factors as fractions, preset applied
+    gf_low_fraction: f64,
+    gf_high_fraction: f64,
+    /// Depths resolved from `pressure_bar` readings
+    pressure_depths: Vec<OutputPressureDepth>,
+    depth_reversals: usize,
+}
+
+/// Parse and validate one input payload without touching the deco model
+fn validate_input(input_json: &str, options: &Options) -> Result<ValidInput, Failure> {
+    if let Ok(SchemaProbe {
+        schema_version: Some(version),
+    }) = serde_json::from_str(input_json)
+    {
+        if version > SCHEMA_VERSION {
+            return Err(Failure::new(
+                ErrorCode::UnsupportedSchemaVersion,
+                format!("schema_version {version} is newer than the supported {SCHEMA_VERSION}"),
+            ));
+        }
+    }
+
+    let mut payload: InputPayload = serde_json::from_str(input_json)
+        .map_err(|e| Failure::new(ErrorCode::InvalidJson, format!("invalid json: {e}")))?;
+
+    if let Some(config) = payload.config.take() {
+        config.apply(&mut payload);
+    }
+
+    // Repetitive dives: the last dive becomes the reported profile
+    let mut prior_dives = std::mem::take(&mut payload.dives);
+    let mut surface_interval_min = 0.0;
+    if let Some(final_dive) = prior_dives.pop() {
+        if !payload.segments.is_empty() {
+            return Err(Failure::new(
+                ErrorCode::SegmentsAndDives,
+                "use either segments or dives, not both",
+            ));
+        }
+        payload.segments = final_dive.segments;
+        surface_interval_min = final_dive.surface_interval_min;
+        if let Some(o2_narcotic) = final_dive.o2_narcotic {
+            payload.o2_narcotic = o2_narcotic;
+        }
+    }
+
+    // dive_deco only ships a Bühlmann model; never fall back silently
+    if payload.algorithm == Algorithm::VpmB {
+        let conservatism = match payload.conservatism {
+            Some(Conservatism::Level(level)) => format!(" +{level}"),
+            _ => String::new(),
+        };
+        return Err(Failure::new(
+            ErrorCode::UnsupportedAlgorithm,
+            format!(
+                "algorithm {}{conservatism} is not available: dive_deco implements {} only",
+                Algorithm::VpmB.model_name(),
+                Algorithm::Buehlmann.model_name()
+            ),
+        ));
+    }
+
+    // Basic validation: every problem is reported under --all-errors, the
+    // first one otherwise
+    let mut failures = Failures::new(options.all_errors);
+    if payload.segments.is_empty() || prior_dives.iter().any(|d| d.segments.is_empty()) {
+        failures.push(Failure::new(ErrorCode::NoSegments, "no segments"))?;
+    }
+    for (d, dive) in prior_dives.iter_mut().enumerate() {
+        if let Err(failure) = durations_from_timestamps(&mut dive.segments, &format!("dives[{d}]."))
+        {
+            failures.push(failure)?;
+        }
+    }
+    if let Err(failure) = durations_from_timestamps(&mut payload.segments, "") {
+        failures.push(failure)?;
+    }
+
+    // Pressure readings become depths in the input's units, so they go
+    // through the same unit and water conversions as depth_m. An invalid
+    // altitude is reported below; sea level stands in until then.
+    let surface_pressure_mbar = payload
+        .altitude_m
+        .filter(|altitude| ALTITUDE_RANGE_M.contains(altitude))
+        .map_or(SEA_LEVEL_PRESSURE_MBAR, altitude_pressure_mbar);
+    let surface_bar = surface_pressure_mbar as f64 / 1000.0;
+    let (water, input_units) = (payload.water, payload.units);
+    let mut pressure_depths = vec![];
+    let prior_segments = prior_dives.iter_mut().enumerate().flat_map(|(d, dive)| {
+        dive.segments
+            .iter_mut()
+            .enumerate()
+            .map(move |(i, seg)| (Some(d), i, seg))
+    });
+    let final_segments = payload
+        .segments
+        .iter_mut()
+        .enumerate()
+        .map(|(i, seg)| (None, i, seg));
+    for (dive, i, seg) in prior_segments.chain(final_segments) {
+        let Some(pressure_bar) = seg.pressure_bar else {
+            continue;
+        };
+        let name = match dive {
+            Some(d) => format!("dives[{d}].segments[{i}]"),
+            None => format!("segments[{i}]"),
+        };
+        if !seg.depth_m.is_nan() {
+            failures.push(Failure::new(
+                ErrorCode::InvalidSegment,
+                format!("{name}: give depth_m or pressure_bar, not both"),
+            ))?;
+            continue;
+        }
+        if !pressure_bar.is_finite() || pressure_bar <= 0.0 {
+            failures.push(Failure::new(
+                ErrorCode::InvalidSegment,
+                format!("{name}: pressure_bar must be a positive number"),
+            ))?;
+            continue;
+        }
+        // Readings a little under the surface pressure are sensor noise
+        let depth_m = water.actual_depth((pressure_bar - surface_bar).max(0.0) * 10.0);
+        seg.depth_m = match input_units {
+            Units::Metric => depth_m,
+            Units::Imperial => units::m_to_feet(depth_m),
+        };
+        if dive.is_none() {
+            pressure_depths.push(OutputPressureDepth {
+                segment_index: i,
+                pressure_bar,
+                depth_m,
+            });
+        }
+    }
+    let prior_segments = prior_dives.iter().enumerate().flat_map(|(d, dive)| {
+        dive.segments
+            .iter()
+            .enumerate()
+            .map(move |(i, seg)| (format!("dives[{d}].segments[{i}]"), seg))
+    });
+    let final_segments = payload
+        .segments
+        .iter()
+        .enumerate()
+        .map(|(i, seg)| (format!("segments[{i}]"), seg));
+    for (name, seg) in prior_segments.chain(final_segments) {
+        if !seg.depth_m.is_finite() || seg.depth_m < 0.0 {
+            failures.push(Failure::new(
+                ErrorCode::InvalidSegment,
+                format!("{name}: depth_m (or pressure_bar) must be a non-negative number"),
+            ))?;
+        }
+        if !seg.duration_min.is_finite() || seg.duration_min <= 0.0 {
+            failures.push(Failure::new(
+                ErrorCode::InvalidSegment,
+                format!("{name}: duration_min must be a positive number"),
+            ))?;
+        } else if seg.duration_min > MAX_SEGMENT_DURATION_MIN {
+            failures.push(Failure::new(
+                ErrorCode::InvalidSegment,
+                format!(
+                    "{name}: duration_min must be at most {MAX_SEGMENT_DURATION_MIN} (30 days)"
+                ),
+            ))?;
+        }
+    }
+
+    let intervals = prior_dives.iter().map(|d| d.surface_interval_min);
+    for interval in intervals.chain(std::iter::once(surface_interval_min)) {
+        if !interval.is_finite() || interval < 0.0 {
+            failures.push(Failure::new(
+                ErrorCode::InvalidSurfaceInterval,
+                "surface_interval_min must be a non-negative number",
+            ))?;
+            // One message covers every value
+            break;
+        }
+    }
+    let input_gases = std::iter::once(&payload.gas)
+        .chain(payload.segments.iter().filter_map(|s| s.gas.as_ref()))
+        .chain(
+            prior_dives
+                .iter()
+                .flat_map(|d| d.segments.iter().filter_map(|s| s.gas.as_ref())),
+        )
+        .chain(payload.deco_gases.iter());
+    // Invalid mixes can't be built, so the segment gas checks are skipped
+    let mut gases_valid = true;
+    for input_gas in input_gases {
+        if !(0.0..=1.0).contains(&input_gas.o2) || !(0.0..=1.0).contains(&input_gas.he) {
+            failures.push(Failure::new(
+                ErrorCode::InvalidGasFractions,
+                "invalid gas fractions",
+            ))?;
+            gases_valid = false;
+            continue;
+        }
+        if input_gas.o2 + input_gas.he > 1.0 {
+            failures.push(Failure::new(
+                ErrorCode::GasFractionsExceedOne,
+                "gas fractions exceed 1.0",
+            ))?;
+            gases_valid = false;
+            continue;
+        }
+        if let Some(n2) = input_gas.n2 {
+            let sum = input_gas.o2 + input_gas.he + n2;
+            // EPSILON keeps e.g. 0.18 + 0.45 + 0.36 exactly at the tolerance
+            let off = (sum - 1.0).abs();
+            if !(0.0..=1.0).contains(&n2) || off > GAS_SUM_TOLERANCE + f64::EPSILON {
+                failures.push(Failure::new(
+                    ErrorCode::GasFractionsDoNotSumToOne,
+                    format!(
+                        "gas fractions o2 {} + he {} + n2 {n2} sum to {sum:.3}, not 1.0",
+                        input_gas.o2, input_gas.he
+                    ),
+                ))?;
+            }
+        }
+    }
+
+    for sac in [payload.sac_l_per_min, payload.stress_sac_l_per_min]
+        .into_iter()
+        .flatten()
+    {
+        if !sac.is_finite() || sac < 0.0 {
+            failures.push(Failure::new(
+                ErrorCode::InvalidSac,
+                "sac_l_per_min and stress_sac_l_per_min must be non-negative numbers",
+            ))?;
+            // One message covers every value
+            break;
+        }
+    }
+
+    for size in [payload.cylinder_l, payload.fill_bar].into_iter().flatten() {
+        if !size.is_finite() || size <= 0.0 {
+            failures.push(Failure::new(
+                ErrorCode::InvalidCylinder,
+                "cylinder_l and fill_bar must be positive numbers",
+            ))?;
+            // One message covers every value
+            break;
+        }
+    }
+
+    for rate in [
+        payload.descent_rate_m_per_min,
+        payload.ascent_rate_m_per_min,
+        Some(payload.max_ascent_rate_m_per_min),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if !rate.is_finite() || rate <= 0.0 {
+            failures.push(Failure::new(
+                ErrorCode::InvalidRate,
+                "ascent and descent rates must be positive numbers",
+            ))?;
+            // One message covers every value
+            break;
+        }
+    }
+
+    if let Some(initial) = &payload.initial_tissues {
+        if initial.len() != tissues::COMPARTMENT_COUNT {
+            failures.push(Failure::new(
+                ErrorCode::InvalidInitialTissues,
+                format!(
+                    "initial_tissues has {} compartments; ZH-L16C has {}",
+                    initial.len(),
+                    tissues::COMPARTMENT_COUNT
+                ),
+            ))?;
+        } else if initial
+            .iter()
+            .flat_map(|t| [t.n2_bar, t.he_bar])
+            .any(|p| !p.is_finite() || p < 0.0)
+        {
+            failures.push(Failure::new(
+                ErrorCode::InvalidInitialTissues,
+                "initial_tissues pressures must be non-negative numbers",
+            ))?;
+        }
+    }
+    if !payload.yo_yo_delta_m.is_finite() || payload.yo_yo_delta_m <= 0.0 {
+        failures.push(Failure::new(
+            ErrorCode::InvalidYoYoDelta,
+            "yo_yo_delta_m must be a positive number",
+        ))?;
+    }
+    if !payload.ceiling_tolerance_m.is_finite() || payload.ceiling_tolerance_m < 0.0 {
+        failures.push(Failure::new(
+            ErrorCode::InvalidCeilingTolerance,
+            "ceiling_tolerance_m must be a non-negative number",
+        ))?;
+    }
+    for exposure in [payload.prior_cns_percent, payload.prior_otu] {
+        if !exposure.is_finite() || exposure < 0.0 {
+            failures.push(Failure::new(
+                ErrorCode::InvalidOxygenExposure,
+                "prior_cns_percent and prior_otu must be non-negative numbers",
+            ))?;
+            // One message covers every value
+            break;
+        }
+    }
+    if !WATER_TEMP_RANGE_C.contains(&payload.water_temp_c) {
+        failures.push(Failure::new(
+            ErrorCode::InvalidWaterTemp,
+            "water_temp_c must be between -2 and 40 degrees",
+        ))?;
+    }
+    if let Some(target) = payload.target_end_m {
+        if !target.is_finite() || target < 0.0 {
+            failures.push(Failure::new(
+                ErrorCode::InvalidTargetEnd,
+                "target_end_m must be a non-negative number",
+            ))?;
+        }
+    }
+
+    for limit in [payload.max_po2, payload.max_deco_po2] {
+        if !limit.is_finite() || limit <= 0.0 {
+            failures.push(Failure::new(
+                ErrorCode::InvalidPo2Limit,
+                "max_po2 and max_deco_po2 must be positive numbers",
+            ))?;
+            // One message covers every value
+            break;
+        }
+    }
+
+    if let Some(altitude) = payload.altitude_m {
+        if !ALTITUDE_RANGE_M.contains(&altitude) {
+            failures.push(Failure::new(
+                ErrorCode::InvalidAltitude,
+                "altitude_m must be between -500 and 5000 meters",
+            ))?;
+        }
+    }
+
+    // The model works in meters; convert imperial depths up front
+    if payload.units == Units::Imperial {
+        let prior_segments = prior_dives.iter_mut().flat_map(|d| d.segments.iter_mut());
+        for seg in payload.segments.iter_mut().chain(prior_segments) {
+            seg.depth_m = units::feet_to_m(seg.depth_m);
+        }
+    }
+    let report_units = payload.units;
+    let depth_label = |depth_m| units::depth_label(report_units, depth_m);
+
+    let mut warnings: Vec<Warning> = vec![];
+
+    if let Some(altitude) = payload.altitude_m.filter(|a| *a > ALTITUDE_WARNING_M) {
+        warnings.push(Warning::new(
+            WarningCode::HighAltitude,
+            format!("altitude {altitude}m is above {ALTITUDE_WARNING_M}m where decompression tables are poorly validated"),
+        ));
+    }
+
+    match payload.conservatism {
+        Some(Conservatism::Level(_)) => {
+            warnings.push(Warning::new(
+                WarningCode::ConservatismIgnored,
+                "conservatism only applies to vpm-b and was ignored",
+            ));
+        }
+        Some(Conservatism::Preset(_)) if payload.gf_low.is_some() && payload.gf_high.is_some() => {
+            warnings.push(Warning::new(
+                WarningCode::ConservatismIgnored,
+                "conservatism preset was ignored: gf_low and gf_high are set",
+            ));
+        }
+        _ => {}
+    }
+
+    if !LAST_STOP_RANGE_M.contains(&payload.last_stop_m) {
+        failures.push(Failure::new(
+            ErrorCode::InvalidLastStop,
+            "last_stop_m must be between 3 and 9 meters",
+        ))?;
+    }
+    if !STANDARD_LAST_STOPS_M.contains(&payload.last_stop_m) {
+        warnings.push(Warning::new(
+            WarningCode::UnusualLastStop,
+            format!(
+                "unusual last stop depth {} (standard is {} or {})",
+                depth_label(payload.last_stop_m),
+                depth_label(STANDARD_LAST_STOPS_M[0]),
+                depth_label(STANDARD_LAST_STOPS_M[1])
+            ),
+        ));
+    }
+
+    // Compute basic metrics
+    let max_depth_m = payload
+        .segments
+        .iter()
+        .map(|s| s.depth_m)
+        .fold(0.0_f64, f64::max);
+
+    // Time at or below the bottom threshold, as recreational tables count it
+    let bottom_time_min = payload
+        .segments
+        .iter()
+        .filter(|s| s.depth_m >= payload.bottom_threshold_m)
+        .fold(0.0, |total, s| total + s.duration_min);
+
+    // The model's depths are seawater depths; in fresh water the same depth
+    // is less pressure, so segments are flown at their seawater equivalent.
+    // Reported depths are converted back to the water dived in.
+    if payload.water != Water::Salt {
+        let prior_segments = prior_dives.iter_mut().flat_map(|d| d.segments.iter_mut());
+        for seg in payload.segments.iter_mut().chain(prior_segments) {
+            seg.depth_m = payload.water.seawater_depth(seg.depth_m);
+        }
+    }
+
+    // A preset fills in whichever gradient factor isn't given explicitly
+    let preset = match payload.conservatism {
+        Some(Conservatism::Preset(preset)) => Some(preset.gradient_factors()),
+        _ => None,
+    };
+    let (Some(mut gf_low_fraction), Some(mut gf_high_fraction)) = (
+        payload.gf_low.or(preset.map(|(low, _)| low)),
+        payload.gf_high.or(preset.map(|(_, high)| high)),
+    ) else {
+        // Nothing past this point can be checked without them
+        return Err(failures.fatal(Failure::new(
+            ErrorCode::InvalidGradientFactors,
+            "gf_low and gf_high are required unless conservatism names a preset (conservative, medium, aggressive)",
+        )));
+    };
+
+    // Gradient factors above 1.0 are integer percentages (30/85); the rest
+    // are fractions
+    for gf in [&mut gf_low_fraction, &mut gf_high_fraction] {
+        if *gf > 1.0 {
+            *gf /= 100.0;
+        }
+    }
+
+    // Convert gradient factors from fractions (0.0-1.0) to integers (0-100)
+    let gf_low = (gf_low_fraction * 100.0).round() as u8;
+    let gf_high = (gf_high_fraction * 100.0).round() as u8;
+
+    // The model's GF arithmetic needs these, so they can't be downgraded to
+    // warnings.
+    // Negative and non-finite values saturate to 0 or 255 above.
+    let gf_range = 1..=100;
+    if !gf_range.contains(&gf_low) || !gf_range.contains(&gf_high) {
+        failures.push(Failure::new(
+            ErrorCode::InvalidGradientFactors,
+            "gf_low and gf_high must be between 1 and 100 (or 0.01 and 1.0)",
+        ))?;
+    } else if gf_low > gf_high {
+        failures.push(Failure::new(
+            ErrorCode::InvalidGradientFactors,
+            format!(
+                "gf_low {gf_low_fraction} is above gf_high {gf_high_fraction}, which is backwards"
+            ),
+        ))?;
+    }
+
+    let mut gf_concerns = vec![];
+    for (name, gf) in [("gf_low", gf_low_fraction), ("gf_high", gf_high_fraction)] {
+        if gf < GF_WARNING_MIN {
+            gf_concerns.push(format!("{name} {gf} is below {GF_WARNING_MIN}"));
+        }
+    }
+    if gf_high == 100 {
+        gf_concerns.push("gf_high 1.0 leaves no conservatism".to_string());
+    }
+    for message in gf_concerns {
+        if options.strict {
+            failures.push(Failure::new(ErrorCode::UnsafeGradientFactors, message))?;
+            continue;
+        }
+        warnings.push(Warning::new(WarningCode::UnsafeGradientFactors, message));
+    }
+
+    // Without a descent rate a dive starts at its first segment's depth, as
+    // truncated profile exports do, which skews the tissue loading
+    if payload.descent_rate_m_per_min.is_none() {
+        let dives = prior_dives
+            .iter()
+            .map(|d| &d.segments)
+            .chain(std::iter::once(&payload.segments));
+        for (i, segments) in dives.enumerate() {
+            let Some(first) = segments.first() else {
+                continue;
+            };
+            if first.depth_m <= SURFACE_START_MAX_M {
+                continue;
+            }
+            let dive = if prior_dives.is_empty() {
+                String::new()
+            } else {
+                format!("dives[{i}].")
+            };
+            let message = format!(
+                "{dive}segments[0] ({}, {} min): starts without a descent from the surface (set descent_rate_m_per_min to model one)",
+                depth_label(payload.water.actual_depth(first.depth_m)),
+                first.duration_min
+            );
+            if options.strict {
+                failures.push(Failure::new(ErrorCode::NoDescent, message))?;
+                continue;
+            }
+            let warning = Warning::new(WarningCode::NoDescent, message);
+            // Segment indexes refer to the reported dive only
+            warnings.push(if i == prior_dives.len() {
+                warning.at_segment(0)
+            } else {
+                warning
+            });
+        }
+    }
+
+    // Repeated ascents and descents (a sawtooth profile) raise DCS risk
+    let depth_reversals = depth_reversals(
+        payload
+            .segments
+            .iter()
+            .map(|s| payload.water.actual_depth(s.depth_m)),
+        payload.yo_yo_delta_m,
+    );
+    if depth_reversals > 1 {
+        warnings.push(Warning::new(
+            WarningCode::YoYoProfile,
+            format!(
+                "yo-yo profile: {depth_reversals} depth reversals of {} or more (a plain dive has 1)",
+                depth_label(payload.yo_yo_delta_m)
+            ),
+        ));
+    }
+
+    if gases_valid {
+        check_segment_gases(&payload, surface_pressure_mbar, &mut warnings);
+    }
+
+    // No-deco dives past 10m should finish with a safety stop around 5m,
+    // both depths as the diver reads them in this water. Whether deco is
+    // required takes the model, so run drops this for dives that need deco.
+    if payload.water.actual_depth(max_depth_m) > SAFETY_STOP_MIN_DIVE_DEPTH_M {
+        let has_safety_stop = payload.segments.iter().any(|s| {
+            SAFETY_STOP_DEPTH_RANGE_M.contains(&payload.water.actual_depth(s.depth_m))
+                && s.duration_min >= SAFETY_STOP_MIN_DURATION_MIN
+        });
+        if !has_safety_stop {
+            warnings.push(Warning::new(
+                WarningCode::NoSafetyStop,
+                format!(
+                    "no safety stop: {SAFETY_STOP_MIN_DURATION_MIN:.0}-5 min at {} is recommended after dives deeper than {}",
+                    depth_label(5.0),
+                    depth_label(SAFETY_STOP_MIN_DIVE_DEPTH_M)
+                ),
+            ));
+        }
+    }
+
+    failures.finish()?;
+
+    Ok(ValidInput {
+        payload,
+        prior_dives,
+        surface_interval_min,
+        warnings,
+        max_depth_m,
+        bottom_time_min,
+        gf_low,
+        gf_high,
+        gf_low_fraction,
+        gf_high_fraction,
+        pressure_depths,
+        depth_reversals,
+    })
+}
+
+/// Input hash and warnings of a payload checked under `--validate-only`
+#[derive(Debug, Serialize)]
+struct ValidationPayload {
+    tool: &'static str,
+    tool_version: &'static str,
+    schema_version: u32,
+    valid: bool,
+    input_hash: String,
+    #[serde(skip_serializing_if = "OutputWarnings::is_empty")]
+    warnings: OutputWarnings,
+
+    /// Warnings were raised under `--strict`, as `run` would report them
+    #[serde(skip)]
+    failed_strict: bool,
+}
+
+fn validate_only(
+    input_json: &str,
+    input_hash: &str,
+    options: &Options,
+) -> Result<ValidationPayload, Failure> {
+    let ValidInput { warnings, .. } = validate_input(input_json, options)?;
+    let failed_strict = options.strict && !warnings.is_empty();
+    Ok(ValidationPayload {
+        tool: "diveops-deco-validate",
+        tool_version: "0.1.0",
+        schema_version: SCHEMA_VERSION,
+        valid: !failed_strict,
+        input_hash: input_hash.to_string(),
+        warnings: OutputWarnings::new(warnings, options),
+        failed_strict,
+    })
+}
+
+/// JSON result for one payload: the deco plan, or only the validation
+/// result under `--validate-only`; paired with whether the plan failed
+/// `--strict` checks
+fn render_json(
+    input_json: &str,
+    input_hash: &str,
+    options: &Options,
+    pretty: bool,
+) -> Result<(String, bool), Failure> {
+    if options.validate_only {
+        let out = validate_only(input_json, input_hash, options)?;
+        let json = to_json(&out, pretty).map_err(serialization_failure)?;
+        return Ok((json, out.failed_strict));
+    }
+    let out = run(input_json, input_hash, options)?;
+    if options.summary {
+        eprintln!("{}", summary_line(&out));
+    }
+    let json = serialize_output(&out, pretty, options).map_err(serialization_failure)?;
+    Ok((json, out.failed_strict))
+}
+
+/// One-line human summary of a plan, for `--summary` on stderr
+fn summary_line(out: &OutputPayload) -> String {
+    let (depth_unit, to_unit): (&str, fn(f64) -> f64) = match out.units {
+        Units::Metric => ("m", |m| m),
+        Units::Imperial => ("ft", units::m_to_feet),
+    };
+    let depth = |m: f64| format!("{:.0}{depth_unit}", to_unit(m));
+
+    let mut parts = vec![
+        format!("max {}", depth(out.max_depth_m)),
+        format!("TTS {:.0}min", out.tts_min),
+    ];
+    match (out.first_stop_m, out.ndl_min) {
+        (Some(first_stop), _) => parts.push(format!("first stop {}", depth(first_stop))),
+        (None, Some(ndl)) => parts.push(format!("NDL {ndl}min")),
+        (None, None) => {}
+    }
+    parts.push(format!("CNS {:.0}%", out.cns_percent));
+    if let Some(used) = out.gas_used_l {
+        parts.push(format!("gas {used:.0}L"));
+    }
+    if let Some(min_gas) = out.min_gas_l {
+        parts.push(format!("min gas {min_gas:.0}L"));
+    }
+    if let Some(remaining) = out.gas_remaining_l {
+        parts.push(format!("remaining {remaining:.0}L"));
+    }
+    parts.push(format!("no-fly {:.0}h", out.no_fly_hours));
+    match out.warnings.len() {
+        0 => {}
+        1 => parts.push("1 warning".to_string()),
+        n => parts.push(format!("{n} warnings")),
+    }
+    parts.join(", ")
+}
+
+/// Parse, validate and compute the deco plan for one input payload
+fn run(input_json: &str, input_hash: &str, options: &Options) -> Result<OutputPayload, Failure> {
+    let ValidInput {
+        payload,
+        prior_dives,
+        surface_interval_min,
+        mut warnings,
+        max_depth_m,
+        bottom_time_min,
+        gf_low,
+        gf_high,
+        gf_low_fraction,
+        gf_high_fraction,
+        pressure_depths,
+        depth_reversals,
+    } = validate_input(input_json, options)?;
+    let depth_label = |depth_m| units::depth_label(payload.units, depth_m);
+
+    let surface_pressure_mbar = payload
+        .altitude_m
+        .map_or(SEA_LEVEL_PRESSURE_MBAR, altitude_pressure_mbar);
+
+    // Configure Bühlmann model with gradient factors and surface pressure
+    let config = BuehlmannConfig::new()
+        .gradient_factors(gf_low, gf_high)
+        .surface_pressure(surface_pressure_mbar);
+    let mut model = TrackedModel::new(config);
+    if let Some(initial) = &payload.initial_tissues {
+        model.load_tissues(initial.iter().map(|t| tissues::Compartment {
+            n2: t.n2_bar,
+            he: t.he_bar,
+        }));
+    }
+
+    // Create default gas mix, used by segments without their own gas
+    let default_gas = Gas::new(payload.gas.o2, payload.gas.he);
+
+    let deco_gases: Vec<Gas> = payload
+        .deco_gases
+        .iter()
+        .map(|g| Gas::new(g.o2, g.he))
+        .collect();
+
+    let mut cns_percent = payload.prior_cns_percent;
+    let mut otu = payload.prior_otu;
+
+    // Earlier dives are flown as planned, deco included, before the interval
+    for dive in &prior_dives {
+        step_surface_interval(&mut model, dive.surface_interval_min);
+        cns_percent = oxtox::cns_after_interval(cns_percent, dive.surface_interval_min);
+        model.start_dive();
+        let mut dive_gases = deco_gases.clone();
+        let mut previous = surface_start(&dive.segments, default_gas);
+        for seg in &dive.segments {
+            let gas = segment_gas(seg, default_gas);
+            if !dive_gases.contains(&gas) {
+                dive_gases.push(gas);
+            }
+            step_segment(&mut model, &payload, previous, seg, &gas);
+            previous = Some((seg.depth_m, gas));
+            let po2 = gas
+                .gas_pressures_compound(ambient_pressure_bar(surface_pressure_mbar, seg.depth_m))
+                .o2;
+            cns_percent += oxtox::cns_percent(po2, seg.duration_min);
+            otu += oxtox::otu(po2, seg.duration_min);
+        }
+        let stages = planner::complete_ascent(
+            &mut model,
+            &dive_gases,
+            payload.last_stop_m,
+            payload.max_deco_po2,
+        );
+        let (stage_cns, stage_otu) = deco_exposure(&stages, surface_pressure_mbar);
+        cns_percent += stage_cns;
+        otu += stage_otu;
+    }
+    step_surface_interval(&mut model, surface_interval_min);
+    cns_percent = oxtox::cns_after_interval(cns_percent, surface_interval_min);
+    model.start_dive();
+    let (cns_at_dive_start_percent, otu_at_dive_start) = if prior_dives.is_empty() {
+        (None, None)
+    } else {
+        (Some(cns_percent), Some(otu))
+    };
+
+    // Table-style residual nitrogen time at this dive's deepest point on air
+    let residual_n2_time_min = if prior_dives.is_empty() {
+        None
+    } else {
+        let deepest = payload
+            .segments
+            .iter()
+            .fold(0.0, |max: f64, s| max.max(s.depth_m));
+        model.tissues.equivalent_time_min(deepest, &Gas::air())
+    };
+
+    // Every distinct mix breathed during the dive is available for deco
+    let mut available_gases = vec![default_gas];
+
+    let mut max_end_m = 0.0_f64;
+    let mut suggestions: Vec<String> = vec![];
+    let mut max_ead_m: Option<f64> = None;
+    let mut max_gas_density_g_per_l = 0.0_f64;
+
+    // Record each segment (step takes depth in meters, duration in seconds)
+    let dive_start_s = model.time();
+    let mut previous = surface_start(&payload.segments, default_gas);
+    let mut elapsed_min = 0.0;
+    let mut segment_po2 = Vec::with_capacity(payload.segments.len());
+    // Model state entering the last segment, for the --solve-* searches
+    let mut before_last = None;
+    // Model state at the end of the last bottom segment, for tts_at_bottom_min
+    let mut leaving_bottom: Option<TrackedModel> = None;
+    let mut trace = options.trace.then(Vec::new);
+    for (i, seg) in payload.segments.iter().enumerate() {
+        let gas = segment_gas(seg, default_gas);
+        if !available_gases.contains(&gas) {
+            available_gases.push(gas);
+        }
+        let solving = options.solve_bottom_time || options.solve_max_depth;
+        if solving && i + 1 == payload.segments.len() {
+            before_last = Some((model.clone(), previous));
+        }
+
+        let at = segment_label(&payload, i, seg);
+
+        // A logged ascent over the previous sample's duration
+        if payload.recorded_profile && i > 0 {
+            let previous_seg = &payload.segments[i - 1];
+            let rise_m = payload
+                .water
+                .actual_depth(previous_seg.depth_m - seg.depth_m);
+            if rise_m > 0.0 && previous_seg.duration_min > 0.0 {
+                let rate = rise_m / previous_seg.duration_min;
+                if rate > payload.max_ascent_rate_m_per_min {
+                    let message = format!(
+                        "segments[{}] -> segments[{i}]: ascent from {} to {} at {rate:.1} m/min exceeds {} m/min",
+                        i - 1,
+                        depth_label(payload.water.actual_depth(previous_seg.depth_m)),
+                        depth_label(payload.water.actual_depth(seg.depth_m)),
+                        payload.max_ascent_rate_m_per_min
+                    );
+                    warnings
+                        .push(Warning::new(WarningCode::AscentRateExceeded, message).at_segment(i));
+                }
+            }
+        }
+
+        // A planned ascent shallower than the stop the model requires skips
+        // it; recorded profiles are checked against the ceiling below instead
+        let ascending = previous.is_some_and(|(depth, _)| seg.depth_m < depth);
+        if ascending && !payload.recorded_profile {
+            let required_stop_m = planner::stop_depth(model.ceiling());
+            if seg.depth_m + SWITCH_DEPTH_EPSILON_M < required_stop_m {
+                let message = format!(
+                    "{at}: ascends to {} past a required stop at {}",
+                    depth_label(payload.water.actual_depth(seg.depth_m)),
+                    depth_label(payload.water.actual_depth(required_stop_m))
+                );
+                warnings.push(Warning::new(WarningCode::SkippedStop, message).at_segment(i));
+            }
+        }
+
+        step_segment(&mut model, &payload, previous, seg, &gas);
+        previous = Some((seg.depth_m, gas));
+        elapsed_min += seg.duration_min;
+        if let Some(trace) = &mut trace {
+            trace.push(OutputTraceEntry {
+                segment_index: i,
+                runtime_min: (model.time() - dive_start_s) as f64 / 60.0,
+                depth_m: payload.water.actual_depth(seg.depth_m),
+                leading_compartment: model.tissues.leading_compartment(),
+                compartments: output_tissues(&model),
+            });
+        }
+        if seg.depth_m >= payload.bottom_threshold_m {
+            leaving_bottom = Some(model.clone());
+        }
+
+        // A logged dive may have been flown above the ceiling
+        if payload.recorded_profile {
+            let ceiling = model.ceiling();
+            if seg.depth_m + payload.ceiling_tolerance_m < ceiling {
+                let message = format!(
+                    "{at}: ceiling violation at {elapsed_min:.1} min (ceiling {})",
+                    depth_label(payload.water.actual_depth(ceiling))
+                );
+                warnings.push(Warning::new(WarningCode::CeilingViolation, message).at_segment(i));
+            }
+        }
+
+        let fractions = gas.gas_pressures_compound(1.0);
+        let po2 = gas
+            .gas_pressures_compound(ambient_pressure_bar(surface_pressure_mbar, seg.depth_m))
+            .o2;
+        segment_po2.push(po2);
+
+        // MOD, hypoxia, END and density were checked with the input
+        let end = segment_end_m(&payload, seg.depth_m, fractions.o2, fractions.he);
+        max_end_m = max_end_m.max(end);
+
+        if let Some(target) = payload.target_end_m.filter(|target| end > *target) {
+            let he = gas::min_he_for_end(
+                seg.depth_m,
+                fractions.o2,
+                payload.water.seawater_depth(target),
+                payload.o2_narcotic,
+            );
+            // Whole percent, rounded up so the suggestion meets the target
+            let he_pct = (he * 100.0 - SWITCH_DEPTH_EPSILON_M).ceil();
+            let o2_pct = (fractions.o2 * 100.0).round();
+            let suggestion = if o2_pct + he_pct <= 100.0 {
+                format!(
+                    "{he_pct:.0}% He ({}) would meet the {} END at {}",
+                    gas::label(o2_pct / 100.0, he_pct / 100.0),
+                    depth_label(target),
+                    depth_label(payload.water.actual_depth(seg.depth_m))
+                )
+            } else {
+                format!(
+                    "no helium addition to {} meets the {} END at {}; lower the O2",
+                    gas::label(fractions.o2, fractions.he),
+                    depth_label(target),
+                    depth_label(payload.water.actual_depth(seg.depth_m))
+                )
+            };
+            if !suggestions.contains(&suggestion) {
+                suggestions.push(suggestion);
+            }
+        }
+
+        // EAD is a nitrox figure; helium mixes use END
+        if fractions.o2 > AIR_O2 && fractions.he == 0.0 {
+            let ead = payload
+                .water
+                .actual_depth(gas::ead_m(seg.depth_m, fractions.o2));
+            max_ead_m = Some(max_ead_m.map_or(ead, |max: f64| max.max(ead)));
+        }
+
+        let density = gas::density_g_per_l(
+            ambient_pressure_bar(surface_pressure_mbar, seg.depth_m),
+            fractions.o2,
+            fractions.he,
+            payload.water_temp_c,
+        );
+        max_gas_density_g_per_l = max_gas_density_g_per_l.max(density);
+
+        // Oxygen exposure on the gas actually breathed
+        cns_percent += oxtox::cns_percent(po2, seg.duration_min);
+        otu += oxtox::otu(po2, seg.duration_min);
+    }
+
+    // Segments plus the travel between them
+    let profile_min = (model.time() - dive_start_s) as f64 / 60.0;
+
+    // Read before the deco ascent, so they explain why deco is needed
+    let leading_compartment = options.verbose.then(|| model.tissues.leading_compartment());
+    let surface_gf = options
+        .verbose
+        .then(|| model.supersaturation().gf_surf / 100.0);
+
+    let tissue_pressures = options.tissues.then(|| output_tissues(&model));
+
+    // Get ceiling (meters) - this is the depth we cannot ascend above
+    let ceiling_m = payload.water.actual_depth(model.ceiling());
+    let deco_required = ceiling_m > 0.0;
+
+    // The stop a diver would act on: the next stop depth, never shallower
+    // than the last stop; rounded from ceiling_m so it's never shallower
+    // than the reported ceiling either
+    let ceiling_rounded_m = if deco_required {
+        planner::stop_depth(ceiling_m).max(payload.last_stop_m)
+    } else {
+        0.0
+    };
+
+    // The deco stops take the place of a safety stop
+    if deco_required {
+        warnings.retain(|w| w.code != WarningCode::NoSafetyStop);
+    }
+
+    // Get NDL (no-deco limit in minutes) - only meaningful if not in deco
+    let ndl_cap = options.ndl_cap.unwrap_or(DEFAULT_NDL_CAP_MIN);
+    let mut ndl_capped = false;
+    let ndl_min: Option<u64> = if !deco_required {
+        let ndl = model.ndl() as u64;
+        // NDL returns Minutes::MAX for surface/shallow, which always hits the cap
+        if ndl > ndl_cap {
+            ndl_capped = true;
+            Some(ndl_cap)
+        } else {
+            Some(ndl)
+        }
+    } else {
+        None
+    };
+
+    // One minute past the NDL the first ceiling appears; its compartment is
+    // the one that limited the NDL
+    let ndl_limiting_compartment = ndl_min
+        .filter(|_| options.verbose && !ndl_capped)
+        .map(|ndl| {
+            let mut probe = model.clone();
+            probe.step(&model.depth(), &((ndl as usize + 1) * 60), &model.gas());
+            probe.tissues.controlling_compartment(gf_high_fraction)
+        });
+
+    let last_segment = before_last.zip(payload.segments.last());
+    let max_ndl_bottom_time_min = last_segment
+        .as_ref()
+        .filter(|_| options.solve_bottom_time)
+        .map(|((start, previous), last)| {
+            let gas = segment_gas(last, default_gas);
+            max_ndl_bottom_time(start, &payload, *previous, last.depth_m, &gas, ndl_cap)
+        });
+    let max_ndl_depth_m = last_segment
+        .as_ref()
+        .filter(|_| options.solve_max_depth)
+        .map(|((start, previous), last)| {
+            let gas = segment_gas(last, default_gas);
+            let depth = max_ndl_depth(start, &payload, *previous, last.duration_min, &gas);
+            // Rounded down to 0.1m so the reported depth is never too deep
+            let depth = payload.water.actual_depth(depth) + SWITCH_DEPTH_EPSILON_M;
+            (depth * 10.0).floor() / 10.0
+        });
+
+    for gas in deco_gases {
+        if !available_gases.contains(&gas) {
+            available_gases.push(gas);
+        }
+    }
+
+    // The model's own clearance time, but never shorter than DAN guidelines
+    let no_fly_hours = nofly::no_fly_hours(
+        &model,
+        &available_gases,
+        payload.last_stop_m,
+        payload.max_deco_po2,
+        gf_high_fraction,
+    )
+    .max(nofly::guideline_hours(
+        deco_required || !prior_dives.is_empty(),
+    ));
+    if no_fly_hours >= NO_FLY_WARNING_HOURS {
+        warnings.push(Warning::new(
+            WarningCode::NoFlyCap,
+            format!(
+                "no-fly time {no_fly_hours:.1}h approaches the {}h cap",
+                nofly::NO_FLY_CAP_HOURS
+            ),
+        ));
+    }
+
+    // Dives within the NDL skip the deco simulation unless --force-deco
+    let plan = |model: &TrackedModel| match planner::direct_ascent(model) {
+        Some(deco) if !options.force_deco => deco,
+        _ => planner::plan_deco(
+            model,
+            &available_gases,
+            payload.last_stop_m,
+            payload.max_deco_po2,
+        ),
+    };
+
+    // The obligation had the diver started up at the end of the bottom
+    let tts_at_bottom_min = leaving_bottom.map(|bottom| plan(&bottom).tts as f64 / 60.0);
+
+    // Calculate deco schedule and TTS
+    let Deco { deco_stages, tts } = plan(&model);
+
+    if deco_required && payload.deco_gases.is_empty() {
+        warnings.push(Warning::new(
+            WarningCode::NoDecoGas,
+            "deco is required but no deco_gases were given; a deco gas such as EAN50 or O2 would shorten the stops",
+        ));
+    }
+
+    // Without oxygen to breathe on the shallow stops, show what it would save
+    let oxygen = Gas::new(1.0, 0.0);
+    if deco_required && !available_gases.contains(&oxygen) {
+        let with_oxygen = [available_gases.as_slice(), &[oxygen]].concat();
+        let oxygen_tts = planner::plan_deco(
+            &model,
+            &with_oxygen,
+            payload.last_stop_m,
+            payload.max_deco_po2,
+        )
+        .tts;
+        let saved_min = tts.saturating_sub(oxygen_tts) as f64 / 60.0;
+        if saved_min > 0.0 {
+            suggestions.push(format!(
+                "adding O2 as a deco gas would shorten the ascent by {saved_min:.1} min (TTS {:.1} -> {:.1} min)",
+                tts as f64 / 60.0,
+                oxygen_tts as f64 / 60.0
+            ));
+        }
+    }
+
+    // The planner switches within the MOD at max_deco_po2, which may allow
+    // more than a switch should; check every planned switch on its own
+    for stage in &deco_stages {
+        if !matches!(stage.stage_type, DecoStageType::GasSwitch) {
+            continue;
+        }
+        if let Some(warning) = deep_switch_warning(&payload, "", stage.gas, stage.end_depth) {
+            warnings.push(warning);
+        }
+    }
+
+    // The deco ascent on the gas the planner chose for each stage, often the
+    // largest share of the exposure
+    let (stage_cns, stage_otu) = deco_exposure(&deco_stages, surface_pressure_mbar);
+    cns_percent += stage_cns;
+    otu += stage_otu;
+    if cns_percent > CNS_WARNING_PERCENT {
+        warnings.push(Warning::new(
+            WarningCode::CnsExceeded,
+            format!("CNS oxygen toxicity {cns_percent:.0}% exceeds {CNS_WARNING_PERCENT:.0}%"),
+        ));
+    }
+
+    // MODs rounded down to 0.1m so the reported depth is never unsafe
+    let mod_m = |gas: Gas, po2: f64| {
+        let depth = payload.water.actual_depth(gas.max_operating_depth(po2));
+        ((depth + SWITCH_DEPTH_EPSILON_M) * 10.0).floor() / 10.0
+    };
+    let deco_gas_switch_depths = payload
+        .deco_gases
+        .iter()
+        .map(|g| OutputDecoGas {
+            o2: g.o2,
+            he: g.he,
+            max_switch_depth_m: mod_m(Gas::new(g.o2, g.he), payload.max_deco_po2),
+        })
+        .collect();
+    let gas_mods = available_gases
+        .iter()
+        .map(|&gas| {
+            let fractions = gas.gas_pressures_compound(1.0);
+            OutputGasMod {
+                o2: fractions.o2,
+                he: fractions.he,
+                max_po2: payload.max_po2,
+                mod_m: mod_m(gas, payload.max_po2),
+                max_deco_po2: payload.max_deco_po2,
+                deco_mod_m: mod_m(gas, payload.max_deco_po2),
+            }
+        })
+        .collect();
+
+    // TTS is in seconds, convert to minutes
+    let exact_tts_min = tts as f64 / 60.0;
+    let rounded = payload.rounded_stops;
+    let tts_min = if rounded {
+        deco_stages
+            .iter()
+            .map(|stage| stage_minutes(stage, rounded))
+            .sum()
+    } else {
+        exact_tts_min
+    };
+
+    let stops = output_stops(&deco_stages, rounded, payload.water);
+    let stages = options.all_stages.then(|| {
+        deco_stages
+            .iter()
+            .map(|stage| OutputStage {
+                stage_type: match stage.stage_type {
+                    DecoStageType::Ascent => OutputStageType::Ascent,
+                    DecoStageType::GasSwitch => OutputStageType::GasSwitch,
+                    DecoStageType::DecoStop => OutputStageType::DecoStop,
+                },
+                start_depth_m: payload.water.actual_depth(stage.start_depth),
+                end_depth_m: payload.water.actual_depth(stage.end_depth),
+                duration_min: stage_minutes(stage, rounded),
+                gas: output_gas(&stage.gas),
+            })
+            .collect()
+    });
+    let exact_stops = rounded.then(|| output_stops(&deco_stages, false, payload.water));
+
+    // Runtime clock from the end of the last segment through the deco stages
+    let mut clock_min = profile_min;
+    let runtime_min = profile_min + tts_min;
+    let mut schedule = vec![];
+    for stage in &deco_stages {
+        let duration_min = stage_minutes(stage, rounded);
+        if matches!(stage.stage_type, DecoStageType::DecoStop) && stage.duration > 0 {
+            schedule.push(OutputScheduleEntry {
+                depth_m: payload.water.actual_depth(stage.start_depth),
+                duration_min,
+                runtime_start_min: clock_min,
+                runtime_end_min: clock_min + duration_min,
+            });
+        }
+        clock_min += duration_min;
+    }
+
+    let total_stop_min = stops
+        .iter()
+        .fold(0.0, |total, stop| total + stop.duration_min);
+
+    let deco_severity = if !deco_required {
+        DecoSeverity::None
+    } else if total_stop_min < MINIMAL_DECO_MAX_STOP_MIN {
+        DecoSeverity::Minimal
+    } else {
+        DecoSeverity::Significant
+    };
+
+    let first_stop_m = stops
+        .iter()
+        .map(|stop| stop.depth_m)
+        .reduce(f64::max)
+        .filter(|_| deco_required);
+
+    let deep_stop_limit_m = max_depth_m * DEEP_STOP_FRACTION;
+    let has_deep_stops = stops.iter().any(|stop| stop.depth_m > deep_stop_limit_m);
+
+    // Gas consumption over the segments and the full deco ascent
+    let gas_usage = payload.sac_l_per_min.map(|sac| {
+        let bottom = payload.segments.iter().map(|s| {
+            (
+                segment_gas(s, default_gas),
+                gas_consumed_l(sac, s.depth_m, s.duration_min),
+            )
+        });
+        let deco = deco_stages.iter().map(|stage| {
+            let mean_depth = (stage.start_depth + stage.end_depth) / 2.0;
+            (
+                stage.gas,
+                gas_consumed_l(sac, mean_depth, stage.duration as f64 / 60.0),
+            )
+        });
+        let mut usage: Vec<(Gas, f64)> = vec![];
+        for (gas, used_l) in bottom.chain(deco) {
+            match usage.iter_mut().find(|(g, _)| *g == gas) {
+                Some((_, total)) => *total += used_l,
+                None => usage.push((gas, used_l)),
+            }
+        }
+        usage
+    });
+    let gas_used_l = gas_usage
+        .as_ref()
+        .map(|usage| usage.iter().map(|(_, used_l)| used_l).sum());
+
+    // Rock bottom: both divers breathe one supply from the deepest point,
+    // through the ascent to where deco starts and the whole deco schedule
+    let min_gas_l = payload.stress_sac_l_per_min.map(|sac| {
+        let final_depth = payload.segments.last().map_or(0.0, |s| s.depth_m);
+        let max_depth = payload.water.seawater_depth(max_depth_m);
+        let ascent_rate = payload
+            .ascent_rate_m_per_min
+            .unwrap_or(planner::DECO_ASCENT_RATE);
+        let to_final_depth_min = (max_depth - final_depth) / ascent_rate;
+        let to_final_depth =
+            gas_consumed_l(sac, (max_depth + final_depth) / 2.0, to_final_depth_min);
+        let deco: f64 = deco_stages
+            .iter()
+            .map(|stage| {
+                let mean_depth = (stage.start_depth + stage.end_depth) / 2.0;
+                gas_consumed_l(sac, mean_depth, stage.duration as f64 / 60.0)
+            })
+            .sum();
+        MIN_GAS_DIVERS * (to_final_depth + deco)
+    });
+
+    // What the cylinder has left after the plan, held against the minimum
+    // gas reserve or, without a stress SAC, a third of the supply
+    let supply_l = payload
+        .cylinder_l
+        .zip(payload.fill_bar)
+        .map(|(volume, pressure)| volume * pressure);
+    let gas_remaining_l = supply_l.zip(gas_used_l).map(|(supply, used)| {
+        let remaining = supply - used;
+        let reserve = min_gas_l.unwrap_or(supply / 3.0);
+        (remaining, reserve)
+    });
+    if let Some((remaining, reserve)) = gas_remaining_l {
+        if remaining < reserve {
+            let message = format!(
+                "planned gas use leaves {remaining:.0} L, below the {reserve:.0} L reserve"
+            );
+            warnings.push(Warning::new(WarningCode::InsufficientGas, message));
+        }
+    }
+
+    // The model's NDL near the surface runs to days; a diver is limited by
+    // far more mundane things
+    let ndl_min = match ndl_min {
+        Some(ndl) if options.ndl_realistic => {
+            let final_depth = payload.segments.last().map_or(0.0, |s| s.depth_m);
+            let gas_limit = payload
+                .sac_l_per_min
+                .filter(|sac| *sac > 0.0)
+                .zip(gas_remaining_l)
+                .map(|(sac, (remaining, reserve)): (f64, (f64, f64))| {
+                    let spare_l = (remaining - reserve).max(0.0);
+                    (spare_l / gas_consumed_l(sac, final_depth, 1.0)).floor() as u64
+                });
+            let limit =
+                gas_limit.map_or(REALISTIC_NDL_CAP_MIN, |gas| gas.min(REALISTIC_NDL_CAP_MIN));
+            if ndl > limit {
+                ndl_capped = true;
+                Some(limit)
+            } else {
+                Some(ndl)
+            }
+        }
+        other => other,
+    };
+
+    let mut out = OutputPayload {
+        tool: "diveops-deco-validate",
+        tool_version: "0.1.0",
+        schema_version: SCHEMA_VERSION,
+        model: payload.algorithm.model_name(),
+        units: payload.units,
+        water: payload.water,
+        gf_low: gf_low_fraction,
+        gf_high: gf_high_fraction,
+        gas: output_gas(&default_gas),
+        surface_pressure_bar: surface_pressure_mbar as f64 / 1000.0,
+        water_density_kg_per_m3: payload.water.density_kg_per_m3(),
         ceiling_m,
+        ceiling_rounded_m,
         tts_min,
+        tts_at_bottom_min,
+        total_stop_min,
+        // Floating-point noise aside, never negative
+        ascent_time_min: (tts_min - total_stop_min).max(0.0),
         ndl_min,
+        ndl_capped,
         deco_required,
+        deco_severity,
+        first_stop_m,
+        has_deep_stops,
         stops,
+        exact_stops,
+        exact_tts_min: rounded.then_some(exact_tts_min),
+        schedule,
+        stages,
+        deco_gas_switch_depths,
+        gas_mods,
+        gf_comparison: None,
         max_depth_m,
+        bottom_time_min,
+        max_ndl_bottom_time_min,
+        max_ndl_depth_m,
         runtime_min,
-        input_hash,
-        warnings: vec![],
+        max_end_m,
+        max_ead_m,
+        o2_narcotic: payload.o2_narcotic,
+        max_gas_density_g_per_l,
+        water_temp_c: payload.water_temp_c,
+        cns_percent,
+        otu,
+        cns_remaining_percent: (oxtox::CNS_DAILY_LIMIT_PERCENT - cns_percent).max(0.0),
+        no_fly_hours,
+        gas_used_l,
+        gas_usage: gas_usage
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(gas, used_l)| {
+                let fractions = gas.gas_pressures_compound(1.0);
+                OutputGasUsage {
+                    o2: fractions.o2,
+                    he: fractions.he,
+                    used_l,
+                }
+            })
+            .collect(),
+        min_gas_l,
+        gas_remaining_l: gas_remaining_l.map(|(remaining, _)| remaining),
+        input_hash: input_hash.to_string(),
+        output_hash: None,
+        pressure_depths,
+        segment_po2: options.verbose.then_some(segment_po2),
+        leading_compartment,
+        surface_gf,
+        ndl_limiting_compartment,
+        tissue_pressures,
+        trace,
+        residual_n2_time_min,
+        cns_at_dive_start_percent,
+        otu_at_dive_start,
+        prior_dives: prior_dives.len(),
+        depth_reversals,
+        failed_strict: options.strict && !warnings.is_empty(),
+        // Warnings are still collected (and escalated under --strict)
+        warnings: OutputWarnings::new(warnings, options),
+        suggestions,
         error: None,
     };
 
-    match serde_json::to_string(&out) {
-        Ok(s) => println!("{s}"),
-        Err(e) => {
-            eprintln!("failed to serialize output: {e}");
-            std::process::exit(7);
-        }
+    if let Some(pair) = &payload.compare_gf {
+        let alt = compare_gf(input_json, pair, options)?;
+        out.gf_comparison = Some(OutputGfComparison {
+            gf_low: alt.gf_low,
+            gf_high: alt.gf_high,
+            tts_min: alt.tts_min,
+            total_stop_min: alt.total_stop_min,
+            total_stop_delta_min: alt.total_stop_min - out.total_stop_min,
+            stops: alt.stops,
+            schedule: alt.schedule,
+        });
     }
+
+    // Hashed before the field is set, so it covers everything but itself
+    let serialized = serialize_output(&out, false, options).map_err(serialization_failure)?;
+    out.output_hash = Some(sha256_hex(&serialized));
+    Ok(out)
 }
@@ -0,0 +1,46 @@
+//! Time to wait at the surface before flying.
+
+use crate::planner;
+use crate::tissues::TrackedModel;
+use dive_deco::{Depth, Gas};
+
+/// Cabin pressure of an airliner pressurized to 8000ft
+const CABIN_PRESSURE_BAR: f64 = 0.753;
+/// Longest surface interval simulated
+pub const NO_FLY_CAP_HOURS: f64 = 24.0;
+/// DAN minimum surface intervals before flying
+const SINGLE_DIVE_HOURS: f64 = 12.0;
+const REPETITIVE_OR_DECO_HOURS: f64 = 18.0;
+/// Surface interval simulation step
+const STEP_SECONDS: usize = 60;
+
+/// Hours on air at the surface, after completing the planned deco, until every
+/// compartment tolerates cabin pressure at `gf` (0.0-1.0), capped at
+/// `NO_FLY_CAP_HOURS`
+pub fn no_fly_hours(
+    model: &TrackedModel,
+    gases: &[Gas],
+    last_stop_m: Depth,
+    switch_po2: f64,
+    gf: f64,
+) -> f64 {
+    let mut sim = model.clone();
+    planner::complete_ascent(&mut sim, gases, last_stop_m, switch_po2);
+
+    let max_steps = (NO_FLY_CAP_HOURS * 3600.0) as usize / STEP_SECONDS;
+    let mut steps = 0;
+    while steps < max_steps && sim.tissues.tolerated_ambient_bar(gf) > CABIN_PRESSURE_BAR {
+        sim.step(&0.0, &STEP_SECONDS, &Gas::air());
+        steps += 1;
+    }
+    (steps * STEP_SECONDS) as f64 / 3600.0
+}
+
+/// DAN guideline wait for a single no-deco dive, or a repetitive or deco dive
+pub fn guideline_hours(repetitive_or_deco: bool) -> f64 {
+    if repetitive_or_deco {
+        REPETITIVE_OR_DECO_HOURS
+    } else {
+        SINGLE_DIVE_HOURS
+    }
+}
@@ -0,0 +1,123 @@
+/// NOAA single-exposure CNS limits: (PO2 bar, limit minutes)
+const NOAA_CNS_LIMITS: [(f64, f64); 11] = [
+    (0.6, 720.0),
+    (0.7, 570.0),
+    (0.8, 450.0),
+    (0.9, 360.0),
+    (1.0, 300.0),
+    (1.1, 240.0),
+    (1.2, 210.0),
+    (1.3, 180.0),
+    (1.4, 150.0),
+    (1.5, 120.0),
+    (1.6, 45.0),
+];
+
+/// PO2 below which no CNS loading accrues
+const CNS_MIN_PO2: f64 = 0.5;
+/// Shortest limit, reached as the last table slope is carried on above
+/// 1.6 bar (matches dive_deco's 400s)
+const CNS_LIMIT_FLOOR_MIN: f64 = 400.0 / 60.0;
+
+/// NOAA single-exposure time limit in minutes for a PO2, if any applies
+fn cns_limit_min(po2: f64) -> Option<f64> {
+    if po2 <= CNS_MIN_PO2 {
+        return None;
+    }
+    let (first_po2, first_limit) = NOAA_CNS_LIMITS[0];
+    if po2 <= first_po2 {
+        return Some(first_limit);
+    }
+    // Linear interpolation between table rows
+    for pair in NOAA_CNS_LIMITS.windows(2) {
+        let ((lo_po2, lo_limit), (hi_po2, hi_limit)) = (pair[0], pair[1]);
+        if po2 <= hi_po2 {
+            let t = (po2 - lo_po2) / (hi_po2 - lo_po2);
+            return Some(lo_limit + t * (hi_limit - lo_limit));
+        }
+    }
+    // Beyond the table, carry on its last slope so the limit keeps falling
+    // smoothly instead of dropping at 1.6 bar
+    let [.., (lo_po2, lo_limit), (hi_po2, hi_limit)] = NOAA_CNS_LIMITS;
+    let slope = (hi_limit - lo_limit) / (hi_po2 - lo_po2);
+    Some((hi_limit + slope * (po2 - hi_po2)).max(CNS_LIMIT_FLOOR_MIN))
+}
+
+/// CNS percent accrued breathing `po2` (bar) for `minutes`
+pub fn cns_percent(po2: f64, minutes: f64) -> f64 {
+    match cns_limit_min(po2) {
+        Some(limit) => minutes / limit * 100.0,
+        None => 0.0,
+    }
+}
+
+/// Daily CNS exposure limit in percent
+pub const CNS_DAILY_LIMIT_PERCENT: f64 = 100.0;
+
+/// Half-time in minutes of CNS recovery at the surface
+const CNS_HALF_TIME_MIN: f64 = 90.0;
+
+/// CNS percent left after `minutes` at the surface. OTU has no such decay;
+/// it's a daily total and carries over in full.
+pub fn cns_after_interval(cns_percent: f64, minutes: f64) -> f64 {
+    cns_percent * 0.5_f64.powf(minutes / CNS_HALF_TIME_MIN)
+}
+
+/// PO2 below which no pulmonary toxicity accrues
+const OTU_MIN_PO2: f64 = 0.5;
+
+/// Oxygen tolerance units (REPEX) accrued breathing `po2` (bar) for `minutes`
+pub fn otu(po2: f64, minutes: f64) -> f64 {
+    if po2 <= OTU_MIN_PO2 {
+        return 0.0;
+    }
+    minutes * ((po2 - OTU_MIN_PO2) / OTU_MIN_PO2).powf(0.83)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(po2: f64) -> f64 {
+        cns_limit_min(po2).unwrap()
+    }
+
+    #[test]
+    fn cns_limit_interpolates_the_noaa_table() {
+        assert_eq!(cns_limit_min(0.5), None);
+        assert_eq!(limit(0.55), 720.0);
+        assert_eq!(limit(1.4), 150.0);
+        assert!((limit(1.45) - 135.0).abs() < 1e-9);
+        assert_eq!(limit(1.6), 45.0);
+    }
+
+    #[test]
+    fn cns_limit_is_continuous_above_the_table() {
+        // Just past the last row the limit carries on falling from 45 min
+        assert!((limit(1.6 + 1e-9) - 45.0).abs() < 1e-3);
+        assert!((limit(1.61) - 37.5).abs() < 1e-9);
+        assert!(limit(1.62) < limit(1.61));
+        assert_eq!(limit(2.0), CNS_LIMIT_FLOOR_MIN);
+    }
+
+    #[test]
+    fn cns_percent_is_minutes_over_the_limit() {
+        assert_eq!(cns_percent(1.6, 45.0), 100.0);
+        assert_eq!(cns_percent(0.4, 600.0), 0.0);
+        // O2 at 6m stays close to its 1.6 bar rate
+        assert!(cns_percent(1.613, 10.0) < 30.0);
+    }
+
+    #[test]
+    fn cns_halves_every_ninety_minutes() {
+        assert_eq!(cns_after_interval(80.0, 90.0), 40.0);
+        assert_eq!(cns_after_interval(80.0, 0.0), 80.0);
+    }
+
+    #[test]
+    fn otu_follows_the_repex_formula() {
+        assert_eq!(otu(1.0, 30.0), 30.0);
+        assert_eq!(otu(0.5, 30.0), 0.0);
+        assert!((otu(1.4, 10.0) - 10.0 * 1.8_f64.powf(0.83)).abs() < 1e-9);
+    }
+}
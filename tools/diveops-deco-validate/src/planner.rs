@@ -0,0 +1,558 @@
+use crate::tissues::TrackedModel;
+use dive_deco::{Deco, DecoStage, DecoStageType, Depth, Gas};
+
+/// Deco stop depth increment (matches dive_deco)
+const STOP_INCREMENT_M: Depth = 3.0;
+/// Ascent rate used for deco ascents (matches dive_deco)
+pub const DECO_ASCENT_RATE: f64 = 9.0;
+/// Slack for float noise in model depths (e.g. 6.000000000000001m)
+const DEPTH_EPSILON: Depth = 1e-6;
+
+/// Round a ceiling up to the next deco stop depth
+pub fn stop_depth(ceiling: Depth) -> Depth {
+    STOP_INCREMENT_M
+        * ((ceiling - DEPTH_EPSILON) / STOP_INCREMENT_M)
+            .ceil()
+            .max(0.0)
+}
+
+/// Deco schedule over all gases, only offering a gas once the diver is at
+/// a stop within its MOD at `switch_po2`.
+///
+/// `simulate_deco` happily ascends to a deco gas' MOD even when the ceiling is
+/// deeper, so gases whose MOD is shallower than the current stop are held back
+/// until the schedule reaches a stop they can be breathed at.
+///
+/// The simulation always finishes deco at 3m; a deeper `last_stop_m` is honored by
+/// holding at that depth until the ceiling clears.
+pub fn plan_deco(model: &TrackedModel, gases: &[Gas], last_stop_m: Depth, switch_po2: f64) -> Deco {
+    let mut sim = model.clone();
+    let mut deco_stages: Vec<DecoStage> = vec![];
+    // Each replan makes at least one more gas usable, so this bounds the loop
+    let mut replans_left = gases.len();
+
+    loop {
+        let first_stop = stop_depth(sim.ceiling());
+        let (usable, held_back): (Vec<Gas>, Vec<Gas>) = gases
+            .iter()
+            .partition(|gas| gas.max_operating_depth(switch_po2) + DEPTH_EPSILON >= first_stop);
+
+        let Deco {
+            deco_stages: stages,
+            ..
+        } = simulate_deco(sim.clone(), &usable, switch_po2);
+
+        // Deepest depth at which a held-back gas becomes breathable
+        let next_gas_mod = held_back
+            .iter()
+            .map(|gas| gas.max_operating_depth(switch_po2))
+            .fold(None, |acc: Option<Depth>, d| {
+                Some(acc.map_or(d, |a| a.max(d)))
+            });
+
+        // First stop reached within that MOD; replan from there
+        let cut = next_gas_mod
+            .filter(|_| replans_left > 0)
+            .and_then(|gas_mod| {
+                stages.iter().position(|stage| {
+                    matches!(stage.stage_type, DecoStageType::DecoStop)
+                        && stage.start_depth <= gas_mod + DEPTH_EPSILON
+                })
+            });
+
+        match cut {
+            Some(index) => {
+                replans_left -= 1;
+                for stage in &stages[..index] {
+                    replay_stage(&mut sim, stage);
+                }
+                deco_stages.extend_from_slice(&stages[..index]);
+            }
+            None => {
+                deco_stages.extend(stages);
+                break;
+            }
+        }
+    }
+
+    if last_stop_m > STOP_INCREMENT_M + DEPTH_EPSILON {
+        deco_stages = hold_last_stop(model, deco_stages, last_stop_m);
+    }
+
+    let tts = deco_stages.iter().map(|stage| stage.duration).sum();
+    Deco { deco_stages, tts }
+}
+
+/// dive_deco's deco simulation: from the current state, switch to the
+/// richest usable gas once in deco, stop in 1s steps within a stop increment
+/// of the ceiling and otherwise ascend to the next stop or gas switch, until
+/// the surface is reached. dive_deco fixes `switch_po2` at 1.6 bar.
+fn simulate_deco(mut sim: TrackedModel, gases: &[Gas], switch_po2: f64) -> Deco {
+    let mut deco = Deco::default();
+    loop {
+        let start_depth = sim.depth();
+        let start_time = sim.time();
+        let start_gas = sim.gas();
+        let ceiling = sim.ceiling();
+        let mut stages = vec![];
+
+        match next_deco_action(&sim, gases, switch_po2) {
+            None => break,
+            Some(DecoAction::AscentToCeiling) => {
+                sim.step_travel_with_rate(&deco_stop_depth(ceiling), &DECO_ASCENT_RATE, &start_gas);
+                stages.push(DecoStage {
+                    stage_type: DecoStageType::Ascent,
+                    start_depth,
+                    end_depth: sim.depth(),
+                    duration: sim.time() - start_time,
+                    gas: sim.gas(),
+                });
+            }
+            Some(DecoAction::AscentToGasSwitch(gas)) => {
+                let switch_depth = gas.max_operating_depth(switch_po2);
+                sim.step_travel_with_rate(&switch_depth, &DECO_ASCENT_RATE, &start_gas);
+                let switch_depth = sim.depth();
+                stages.push(DecoStage {
+                    stage_type: DecoStageType::Ascent,
+                    start_depth,
+                    end_depth: switch_depth,
+                    duration: sim.time() - start_time,
+                    gas: start_gas,
+                });
+                sim.step(&switch_depth, &0, &gas);
+                stages.push(DecoStage {
+                    stage_type: DecoStageType::GasSwitch,
+                    start_depth: switch_depth,
+                    end_depth: switch_depth,
+                    duration: 0,
+                    gas,
+                });
+            }
+            Some(DecoAction::SwitchGas(gas)) => {
+                sim.step(&start_depth, &0, &gas);
+                stages.push(DecoStage {
+                    stage_type: DecoStageType::GasSwitch,
+                    start_depth,
+                    end_depth: start_depth,
+                    duration: 0,
+                    gas,
+                });
+            }
+            Some(DecoAction::Stop) => {
+                sim.step(&start_depth, &1, &start_gas);
+                stages.push(DecoStage {
+                    stage_type: DecoStageType::DecoStop,
+                    start_depth,
+                    end_depth: sim.depth(),
+                    duration: sim.time() - start_time,
+                    gas: sim.gas(),
+                });
+            }
+        }
+
+        for stage in stages {
+            // Consecutive 1s stops merge into one
+            match deco.deco_stages.last_mut() {
+                Some(last)
+                    if matches!(stage.stage_type, DecoStageType::DecoStop)
+                        && matches!(last.stage_type, DecoStageType::DecoStop) =>
+                {
+                    last.duration += stage.duration;
+                }
+                _ => deco.deco_stages.push(stage),
+            }
+            deco.tts += stage.duration;
+        }
+    }
+    deco
+}
+
+enum DecoAction {
+    AscentToCeiling,
+    AscentToGasSwitch(Gas),
+    SwitchGas(Gas),
+    Stop,
+}
+
+/// Next step of `simulate_deco`; None once at the surface
+fn next_deco_action(sim: &TrackedModel, gases: &[Gas], switch_po2: f64) -> Option<DecoAction> {
+    let depth = sim.depth();
+    if depth <= 0. {
+        return None;
+    }
+    if !sim.in_deco() {
+        return Some(DecoAction::AscentToCeiling);
+    }
+
+    let switch_gas = next_switch_gas(sim, gases);
+    if let Some(gas) = switch_gas {
+        if gas != sim.gas() && depth <= gas.max_operating_depth(switch_po2) {
+            return Some(DecoAction::SwitchGas(gas));
+        }
+    }
+
+    if depth - sim.ceiling() <= STOP_INCREMENT_M {
+        Some(DecoAction::Stop)
+    } else if let Some(gas) = switch_gas {
+        Some(DecoAction::AscentToGasSwitch(gas))
+    } else {
+        Some(DecoAction::AscentToCeiling)
+    }
+}
+
+/// Leanest of the gases with a higher PO2 than the current one at the current
+/// depth, whether or not it can be breathed there yet
+fn next_switch_gas(sim: &TrackedModel, gases: &[Gas]) -> Option<Gas> {
+    let depth = sim.depth();
+    let surface_pressure = sim.surface_pressure_mbar();
+    let current_po2 = sim.gas().partial_pressures(&depth, surface_pressure).o2;
+    let mut richer: Vec<Gas> = gases
+        .iter()
+        .copied()
+        .filter(|gas| gas.partial_pressures(&depth, surface_pressure).o2 > current_po2)
+        .collect();
+    richer.sort_by(|a, b| {
+        let a = a.gas_pressures_compound(1.).o2;
+        let b = b.gas_pressures_compound(1.).o2;
+        a.partial_cmp(&b).unwrap()
+    });
+    richer.first().copied()
+}
+
+/// Ceiling rounded up to a stop depth without `stop_depth`'s slack, as the
+/// simulation rounds it
+fn deco_stop_depth(ceiling: Depth) -> Depth {
+    STOP_INCREMENT_M * (ceiling / STOP_INCREMENT_M).ceil()
+}
+
+/// What `simulate_deco` returns when there is no obligation: one ascent to the
+/// surface on the current gas. None when deco is required.
+pub fn direct_ascent(model: &TrackedModel) -> Option<Deco> {
+    if model.in_deco() {
+        return None;
+    }
+    let depth = model.depth();
+    let deco_stages = if depth > 0.0 {
+        vec![DecoStage {
+            stage_type: DecoStageType::Ascent,
+            start_depth: depth,
+            end_depth: 0.0,
+            // Truncated to whole seconds as the model's travel is
+            duration: (depth / DECO_ASCENT_RATE * 60.0) as usize,
+            gas: model.gas(),
+        }]
+    } else {
+        vec![]
+    };
+    let tts = deco_stages.iter().map(|stage| stage.duration).sum();
+    Some(Deco { deco_stages, tts })
+}
+
+/// Replace the stops shallower than `last_stop_m` with a single hold at that
+/// depth lasting until the ceiling clears, followed by a direct ascent.
+fn hold_last_stop(
+    model: &TrackedModel,
+    stages: Vec<DecoStage>,
+    last_stop_m: Depth,
+) -> Vec<DecoStage> {
+    // Nothing to do unless the schedule stops shallower than the last stop
+    let stops_shallower = stages.iter().any(|stage| {
+        matches!(stage.stage_type, DecoStageType::DecoStop)
+            && stage.start_depth + DEPTH_EPSILON < last_stop_m
+    });
+    if !stops_shallower {
+        return stages;
+    }
+    let leave_index = stages
+        .iter()
+        .position(|stage| stage.end_depth + DEPTH_EPSILON < last_stop_m)
+        .unwrap_or(stages.len());
+
+    let mut sim = model.clone();
+    let mut kept: Vec<DecoStage> = stages[..leave_index].to_vec();
+    for stage in &kept {
+        replay_stage(&mut sim, stage);
+    }
+
+    let gas = sim.gas();
+
+    // Finish the ascent to the last stop if the schedule skipped past it
+    let depth = sim.depth();
+    if depth > last_stop_m + DEPTH_EPSILON {
+        let start_time = sim.time();
+        sim.step_travel_with_rate(&last_stop_m, &DECO_ASCENT_RATE, &gas);
+        kept.push(DecoStage {
+            stage_type: DecoStageType::Ascent,
+            start_depth: depth,
+            end_depth: last_stop_m,
+            duration: sim.time() - start_time,
+            gas,
+        });
+    }
+
+    // Hold in 1s steps until no ceiling remains
+    let hold_depth = sim.depth();
+    let hold_start = sim.time();
+    while sim.in_deco() {
+        sim.step(&hold_depth, &1, &gas);
+    }
+    let hold = sim.time() - hold_start;
+    match kept.last_mut() {
+        Some(last) if matches!(last.stage_type, DecoStageType::DecoStop) => last.duration += hold,
+        _ => kept.push(DecoStage {
+            stage_type: DecoStageType::DecoStop,
+            start_depth: hold_depth,
+            end_depth: hold_depth,
+            duration: hold,
+            gas,
+        }),
+    }
+
+    let start_time = sim.time();
+    sim.step_travel_with_rate(&0.0, &DECO_ASCENT_RATE, &gas);
+    kept.push(DecoStage {
+        stage_type: DecoStageType::Ascent,
+        start_depth: hold_depth,
+        end_depth: 0.0,
+        duration: sim.time() - start_time,
+        gas,
+    });
+
+    kept
+}
+
+/// Apply a planned deco stage to a simulation model the way `simulate_deco` does
+fn replay_stage(sim: &mut TrackedModel, stage: &DecoStage) {
+    match stage.stage_type {
+        DecoStageType::Ascent => {
+            sim.step_travel_with_rate(&stage.end_depth, &DECO_ASCENT_RATE, &stage.gas);
+        }
+        DecoStageType::GasSwitch => {
+            sim.step(&stage.end_depth, &0, &stage.gas);
+        }
+        DecoStageType::DecoStop => {
+            // Stops are built from 1s steps; replay them the same way
+            for _ in 0..stage.duration {
+                sim.step(&stage.start_depth, &1, &stage.gas);
+            }
+        }
+    }
+}
+
+/// Carry out the planned deco on the model itself, leaving it at the surface;
+/// returns the stages flown
+pub fn complete_ascent(
+    model: &mut TrackedModel,
+    gases: &[Gas],
+    last_stop_m: Depth,
+    switch_po2: f64,
+) -> Vec<DecoStage> {
+    let Deco { deco_stages, .. } = plan_deco(model, gases, last_stop_m, switch_po2);
+    for stage in &deco_stages {
+        replay_stage(model, stage);
+    }
+    deco_stages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::water::Water;
+    use dive_deco::{BuehlmannConfig, BuehlmannModel, DecoModel};
+
+    fn air_dive(depth: Depth, minutes: usize) -> TrackedModel {
+        let mut model = TrackedModel::new(BuehlmannConfig::new().gradient_factors(30, 70));
+        model.step(&depth, &(minutes * 60), &Gas::air());
+        model
+    }
+
+    fn stops(deco: &Deco) -> Vec<&DecoStage> {
+        deco.deco_stages
+            .iter()
+            .filter(|stage| matches!(stage.stage_type, DecoStageType::DecoStop))
+            .collect()
+    }
+
+    #[test]
+    fn stop_depth_rounds_up_to_the_next_increment() {
+        assert_eq!(stop_depth(0.0), 0.0);
+        assert_eq!(stop_depth(0.4), 3.0);
+        assert_eq!(stop_depth(3.0), 3.0);
+        assert_eq!(stop_depth(6.000000000000001), 6.0);
+        assert_eq!(stop_depth(6.1), 9.0);
+    }
+
+    #[test]
+    fn direct_ascent_only_within_the_ndl() {
+        let deco = direct_ascent(&air_dive(18., 20)).unwrap();
+        assert_eq!(deco.tts, 120);
+        assert_eq!(deco.deco_stages.len(), 1);
+        assert!(direct_ascent(&air_dive(40., 30)).is_none());
+    }
+
+    #[test]
+    fn last_stop_holds_until_the_ceiling_clears() {
+        let model = air_dive(40., 30);
+        let standard = plan_deco(&model, &[Gas::air()], 3., 1.6);
+        let held = plan_deco(&model, &[Gas::air()], 6., 1.6);
+        assert_eq!(stops(&standard).last().unwrap().start_depth, 3.);
+        assert!(stops(&held)
+            .iter()
+            .all(|stop| stop.start_depth >= 6. - DEPTH_EPSILON));
+        let last = held.deco_stages.last().unwrap();
+        assert!(matches!(last.stage_type, DecoStageType::Ascent));
+        assert_eq!((last.start_depth, last.end_depth), (6., 0.));
+
+        // Flying the held schedule leaves no ceiling at the surface
+        let mut sim = model.clone();
+        held.deco_stages
+            .iter()
+            .for_each(|stage| replay_stage(&mut sim, stage));
+        assert_eq!(sim.depth(), 0.);
+        assert!(!sim.in_deco());
+    }
+
+    /// Steps both models through `profile`, then compares our deco
+    /// simulation with dive_deco's over `gases`; returns ours
+    fn assert_deco_matches_dive_deco(
+        config: BuehlmannConfig,
+        profile: &[(Depth, usize, Gas)],
+        gases: &[Gas],
+    ) -> Deco {
+        let mut reference = BuehlmannModel::new(config);
+        let mut model = TrackedModel::new(config);
+        for (depth, seconds, gas) in profile {
+            reference.step_travel_with_rate(depth, &DECO_ASCENT_RATE, gas);
+            model.step_travel_with_rate(depth, &DECO_ASCENT_RATE, gas);
+            reference.step(depth, seconds, gas);
+            model.step(depth, seconds, gas);
+        }
+
+        let expected = reference.deco(gases.to_vec());
+        let actual = simulate_deco(model, gases, 1.6);
+        assert_eq!(actual.tts, expected.tts);
+        assert_eq!(
+            format!("{:?}", actual.deco_stages),
+            format!("{:?}", expected.deco_stages)
+        );
+        actual
+    }
+
+    #[test]
+    fn simulation_matches_dive_deco() {
+        let tmx = Gas::new(0.21, 0.35);
+        assert_deco_matches_dive_deco(
+            BuehlmannConfig::new().gradient_factors(30, 70),
+            &[(45., 25 * 60, tmx)],
+            &[tmx, Gas::new(0.5, 0.), Gas::new(1., 0.)],
+        );
+    }
+
+    #[test]
+    fn simulation_matches_dive_deco_over_several_switches() {
+        let bottom = Gas::new(0.12, 0.6);
+        let travel = Gas::new(0.21, 0.35);
+        let deco = assert_deco_matches_dive_deco(
+            BuehlmannConfig::new().gradient_factors(30, 80),
+            &[(80., 15 * 60, bottom)],
+            &[bottom, travel, Gas::new(0.5, 0.), Gas::new(1., 0.)],
+        );
+        let switches = deco
+            .deco_stages
+            .iter()
+            .filter(|stage| matches!(stage.stage_type, DecoStageType::GasSwitch));
+        assert_eq!(switches.count(), 3);
+    }
+
+    #[test]
+    fn simulation_matches_dive_deco_at_altitude() {
+        let air = Gas::air();
+        assert_deco_matches_dive_deco(
+            BuehlmannConfig::new()
+                .gradient_factors(30, 70)
+                .surface_pressure(800),
+            &[(35., 25 * 60, air)],
+            &[air, Gas::new(0.5, 0.)],
+        );
+    }
+
+    #[test]
+    fn simulation_matches_dive_deco_at_fresh_water_depths() {
+        let air = Gas::air();
+        assert_deco_matches_dive_deco(
+            BuehlmannConfig::new().gradient_factors(40, 85),
+            &[(Water::Fresh.seawater_depth(40.), 30 * 60, air)],
+            &[air, Gas::new(1., 0.)],
+        );
+    }
+
+    /// A second dive after a surface interval; `start_dive` isn't called, as
+    /// dive_deco has no equivalent
+    #[test]
+    fn simulation_matches_dive_deco_on_a_repetitive_dive() {
+        let air = Gas::air();
+        assert_deco_matches_dive_deco(
+            BuehlmannConfig::new().gradient_factors(30, 70),
+            &[
+                (30., 20 * 60, air),
+                (5., 180, air),
+                (0., 45 * 60, air),
+                (30., 25 * 60, air),
+            ],
+            &[air],
+        );
+    }
+
+    /// A 6m last stop follows dive_deco's schedule down to 6m, then holds
+    /// there until dive_deco's own ceiling clears
+    #[test]
+    fn last_stop_hold_matches_dive_deco() {
+        let config = BuehlmannConfig::new().gradient_factors(30, 70);
+        let air = Gas::air();
+        let mut reference = BuehlmannModel::new(config);
+        let mut model = TrackedModel::new(config);
+        reference.step(&40., &(30 * 60), &air);
+        model.step(&40., &(30 * 60), &air);
+
+        let mut expected: Vec<DecoStage> = reference
+            .deco(vec![air])
+            .deco_stages
+            .into_iter()
+            .take_while(|stage| stage.end_depth + DEPTH_EPSILON >= 6.)
+            .collect();
+        for stage in &expected {
+            match stage.stage_type {
+                DecoStageType::Ascent => {
+                    reference.step_travel_with_rate(&stage.end_depth, &DECO_ASCENT_RATE, &stage.gas)
+                }
+                DecoStageType::GasSwitch => reference.step(&stage.end_depth, &0, &stage.gas),
+                DecoStageType::DecoStop => {
+                    for _ in 0..stage.duration {
+                        reference.step(&stage.start_depth, &1, &stage.gas);
+                    }
+                }
+            }
+        }
+        let mut hold = 0;
+        while reference.ceiling() > 0. {
+            reference.step(&6., &1, &air);
+            hold += 1;
+        }
+        assert!(hold > 0);
+        let last_stop = expected.last_mut().unwrap();
+        assert!(matches!(last_stop.stage_type, DecoStageType::DecoStop));
+        last_stop.duration += hold;
+        let start_time = reference.dive_state().time;
+        reference.step_travel_with_rate(&0., &DECO_ASCENT_RATE, &air);
+        expected.push(DecoStage {
+            stage_type: DecoStageType::Ascent,
+            start_depth: 6.,
+            end_depth: 0.,
+            duration: reference.dive_state().time - start_time,
+            gas: air,
+        });
+
+        let held = plan_deco(&model, &[air], 6., 1.6);
+        assert_eq!(format!("{:?}", held.deco_stages), format!("{expected:?}"));
+    }
+}
@@ -0,0 +1,550 @@
+//! Compartment inert gas loading and the Bühlmann GF model computed on it.
+//!
+//! dive_deco keeps its compartments private, so `TrackedModel` runs the
+//! ZH-L16C Haldane loading itself, using dive_deco's step sizes, inspired gas
+//! pressures and gradient factor rules.
+
+use dive_deco::{BuehlmannConfig, Depth, Gas, Minutes, Seconds, Supersaturation};
+
+/// Compartments of the ZH-L16C model
+pub const COMPARTMENT_COUNT: usize = 16;
+
+/// Minutes `ndl` simulates before giving up (matches dive_deco)
+const NDL_CUT_OFF_MIN: Minutes = 99;
+
+/// ZH-L16C (N2 half-time, N2 a, N2 b, He half-time, He a, He b), as in dive_deco
+const ZHL_16C: [(f64, f64, f64, f64, f64, f64); COMPARTMENT_COUNT] = [
+    (4., 1.2599, 0.5050, 1.51, 1.7424, 0.4245),
+    (8., 1., 0.6514, 3.02, 1.3830, 0.5747),
+    (12.5, 0.8618, 0.7222, 4.72, 1.1919, 0.6527),
+    (18.5, 0.7562, 0.7825, 6.99, 1.0458, 0.7223),
+    (27., 0.6200, 0.8126, 10.21, 0.9220, 0.7582),
+    (38.3, 0.5043, 0.8434, 14.48, 0.8205, 0.7957),
+    (54.3, 0.4410, 0.8693, 20.53, 0.7305, 0.8279),
+    (77., 0.4000, 0.8910, 29.11, 0.6502, 0.8553),
+    (109., 0.3750, 0.9092, 41.2, 0.5950, 0.8757),
+    (146., 0.3500, 0.9222, 55.19, 0.5545, 0.8903),
+    (187., 0.3295, 0.9319, 70.69, 0.5333, 0.8997),
+    (239., 0.3065, 0.9403, 90.34, 0.5189, 0.9073),
+    (305., 0.2835, 0.9477, 115.29, 0.5181, 0.9122),
+    (390., 0.2610, 0.9544, 147.42, 0.5176, 0.9171),
+    (498., 0.2480, 0.9602, 188.24, 0.5172, 0.9217),
+    (635., 0.2327, 0.9653, 240.03, 0.5119, 0.9267),
+];
+
+/// Inert gas pressures of one compartment in bar
+#[derive(Debug, Clone, Copy)]
+pub struct Compartment {
+    pub n2: f64,
+    pub he: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tissues {
+    pub compartments: [Compartment; COMPARTMENT_COUNT],
+    surface_pressure_mbar: u16,
+}
+
+impl Tissues {
+    /// Compartments saturated with air at 1 bar, as dive_deco initializes them
+    fn new(surface_pressure_mbar: u16) -> Self {
+        Self {
+            compartments: [Compartment { n2: 0.79, he: 0.0 }; COMPARTMENT_COUNT],
+            surface_pressure_mbar,
+        }
+    }
+
+    /// Lowest ambient pressure (bar) every compartment tolerates at a
+    /// gradient factor (0.0-1.0), from the ZH-L16C a/b coefficients
+    pub fn tolerated_ambient_bar(&self, gf: f64) -> f64 {
+        self.tolerated_ambient_by_compartment(gf)
+            .fold(0.0, f64::max)
+    }
+
+    /// 1-based number of the compartment that sets the ceiling at a
+    /// gradient factor (0.0-1.0)
+    pub fn controlling_compartment(&self, gf: f64) -> usize {
+        self.tolerated_ambient_by_compartment(gf)
+            .enumerate()
+            .fold((0, f64::MIN), |best, (i, tolerated)| {
+                if tolerated > best.1 {
+                    (i, tolerated)
+                } else {
+                    best
+                }
+            })
+            .0
+            + 1
+    }
+
+    fn tolerated_ambient_by_compartment(&self, gf: f64) -> impl Iterator<Item = f64> + '_ {
+        self.compartments
+            .iter()
+            .zip(ZHL_16C.iter())
+            .map(move |(comp, params)| {
+                let (_, n2_a, n2_b, _, he_a, he_b) = *params;
+                let inert = comp.n2 + comp.he;
+                // Coefficients weighted by each gas' share of the loading
+                let a = (n2_a * comp.n2 + he_a * comp.he) / inert;
+                let b = (n2_b * comp.n2 + he_b * comp.he) / inert;
+                (inert - a * gf) / (gf / b - gf + 1.0)
+            })
+    }
+
+    /// 1-based number of the compartment closest to its surface M-value,
+    /// the one that would limit a direct ascent
+    pub fn leading_compartment(&self) -> usize {
+        let p_surf = self.surface_pressure_mbar as f64 / 1000.0;
+        self.compartments
+            .iter()
+            .zip(ZHL_16C.iter())
+            .map(|(comp, params)| {
+                let (_, n2_a, n2_b, _, he_a, he_b) = *params;
+                let inert = comp.n2 + comp.he;
+                let a = (n2_a * comp.n2 + he_a * comp.he) / inert;
+                let b = (n2_b * comp.n2 + he_b * comp.he) / inert;
+                let m_value_surf = a + p_surf / b;
+                (inert - p_surf) / (m_value_surf - p_surf)
+            })
+            .enumerate()
+            .fold(
+                (0, f64::MIN),
+                |best, (i, gf)| {
+                    if gf > best.1 {
+                        (i, gf)
+                    } else {
+                        best
+                    }
+                },
+            )
+            .0
+            + 1
+    }
+
+    /// Minutes a surface-saturated diver would need on `gas` at `depth` for
+    /// every compartment to reach this loading: a residual nitrogen time.
+    ///
+    /// None when some compartment holds more than that depth can load it to.
+    pub fn equivalent_time_min(&self, depth: Depth, gas: &Gas) -> Option<f64> {
+        let inspired = gas.inspired_partial_pressures(&depth, self.surface_pressure_mbar);
+        let inspired = inspired.n2 + inspired.he;
+        let fresh = Tissues::new(self.surface_pressure_mbar);
+        self.compartments
+            .iter()
+            .zip(fresh.compartments.iter())
+            .zip(ZHL_16C.iter())
+            .map(|((comp, start), params)| {
+                let (n2_half_time, _, _, _, _, _) = *params;
+                let residual = comp.n2 + comp.he;
+                let start = start.n2 + start.he;
+                if residual <= start {
+                    Some(0.0)
+                } else if residual >= inspired {
+                    None
+                } else {
+                    // Haldane loading solved for time
+                    Some(n2_half_time * ((inspired - start) / (inspired - residual)).log2())
+                }
+            })
+            .try_fold(0.0, |max: f64, t| t.map(|t| max.max(t)))
+    }
+
+    /// Lowest ambient pressure (bar) compartment `index` tolerates at a
+    /// gradient factor in percent, with dive_deco's arithmetic
+    fn min_tolerable_bar(&self, index: usize, gf: u8) -> f64 {
+        let (_, a, b) = self.weighted_params(index);
+        let gf = gf as f64 / 100.;
+        let a_adjusted = a * gf;
+        let b_adjusted = b / (gf - (gf * b) + b);
+        (self.inert_bar(index) - a_adjusted) * b_adjusted
+    }
+
+    /// Highest gradient factor (percent) across compartments at `depth` and
+    /// at the surface
+    fn supersaturation(&self, depth: Depth) -> Supersaturation {
+        let p_surf = self.surface_pressure_mbar as f64 / 1000.;
+        let p_amb = p_surf + (depth / 10.);
+        (0..COMPARTMENT_COUNT).fold(
+            Supersaturation {
+                gf_99: 0.,
+                gf_surf: 0.,
+            },
+            |acc, i| {
+                let (_, a, b) = self.weighted_params(i);
+                let inert = self.inert_bar(i);
+                let m_value = a + (p_amb / b);
+                let m_value_surf = a + (p_surf / b);
+                let gf_99 = ((inert - p_amb) / (m_value - p_amb)) * 100.;
+                let gf_surf = ((inert - p_surf) / (m_value_surf - p_surf)) * 100.;
+                Supersaturation {
+                    gf_99: acc.gf_99.max(gf_99),
+                    gf_surf: acc.gf_surf.max(gf_surf),
+                }
+            },
+        )
+    }
+
+    /// Deepest depth, in 1m steps up from `depth`, before some compartment
+    /// reaches gf_low: the GF low anchor
+    fn gf_low_depth(&self, depth: Depth, gf_low: u8) -> Depth {
+        let mut anchor = depth;
+        while anchor > 0. {
+            let next = (anchor - 1.).max(0.);
+            if self.supersaturation(next).gf_99 >= gf_low.into() {
+                break;
+            }
+            anchor = next;
+        }
+        anchor
+    }
+
+    fn inert_bar(&self, index: usize) -> f64 {
+        let comp = &self.compartments[index];
+        comp.he + comp.n2
+    }
+
+    /// (half-time, a, b) weighted by each gas' share of the loading
+    fn weighted_params(&self, index: usize) -> (f64, f64, f64) {
+        let comp = &self.compartments[index];
+        let (n2_half_time, n2_a, n2_b, he_half_time, he_a, he_b) = ZHL_16C[index];
+        let weighted = |he_param: f64, n2_param: f64| {
+            ((he_param * comp.he) + (n2_param * comp.n2)) / (comp.he + comp.n2)
+        };
+        (
+            weighted(he_half_time, n2_half_time),
+            weighted(he_a, n2_a),
+            weighted(he_b, n2_b),
+        )
+    }
+
+    /// Haldane loading for `seconds` at a constant depth
+    fn step(&mut self, depth: Depth, seconds: Seconds, gas: &Gas) {
+        let inspired = gas.inspired_partial_pressures(&depth, self.surface_pressure_mbar);
+        let minutes = seconds as f64 / 60.0;
+        for (comp, params) in self.compartments.iter_mut().zip(ZHL_16C.iter()) {
+            let (n2_half_time, _, _, he_half_time, _, _) = *params;
+            comp.n2 += (inspired.n2 - comp.n2) * (1.0 - 2_f64.powf(-minutes / n2_half_time));
+            comp.he += (inspired.he - comp.he) * (1.0 - 2_f64.powf(-minutes / he_half_time));
+        }
+    }
+}
+
+/// Bühlmann ZH-L16C model with gradient factors, computed on `Tissues`.
+///
+/// Follows dive_deco's `BuehlmannModel` step for step, so plans match it,
+/// but owns its state: dive_deco keeps the loading and the GF low anchor
+/// private and can neither start from a given loading nor start a new dive
+/// from the one it carries.
+#[derive(Debug, Clone)]
+pub struct TrackedModel {
+    config: BuehlmannConfig,
+    pub tissues: Tissues,
+    /// Lowest tolerated ambient pressure (bar) per compartment as of the last
+    /// step: at gf_high, except the leading compartment at the GF in effect
+    min_tolerable: [f64; COMPARTMENT_COUNT],
+    depth: Depth,
+    time: Seconds,
+    gas: Gas,
+    /// Depth where the GF slope starts, fixed when deco is first required
+    gf_low_depth: Option<Depth>,
+}
+
+impl TrackedModel {
+    /// `config` is taken as valid: gradient factors 1-100, gf_low at most
+    /// gf_high
+    pub fn new(config: BuehlmannConfig) -> Self {
+        let tissues = Tissues::new(config.surface_pressure);
+        let mut model = Self {
+            config,
+            min_tolerable: [0.0; COMPARTMENT_COUNT],
+            tissues,
+            depth: 0.0,
+            time: 0,
+            gas: Gas::air(),
+            gf_low_depth: None,
+        };
+        for i in 0..COMPARTMENT_COUNT {
+            model.min_tolerable[i] = model.tissues.min_tolerable_bar(i, config.gf.1);
+        }
+        model
+    }
+
+    pub fn step(&mut self, depth: &Depth, time: &Seconds, gas: &Gas) {
+        self.depth = *depth;
+        self.gas = *gas;
+        self.time += time;
+        self.recalculate(*depth, *time, gas);
+    }
+
+    /// Linear travel in 1s steps; like dive_deco, the model depth only moves
+    /// to the target once the travel is done
+    pub fn step_travel_with_rate(&mut self, target_depth: &Depth, rate: &f64, gas: &Gas) {
+        let seconds = ((target_depth - self.depth).abs() / rate * 60.) as Seconds;
+        self.gas = *gas;
+        let dist_rate = (target_depth - self.depth) / seconds as f64;
+        let mut depth = self.depth;
+        for _ in 0..seconds {
+            self.time += 1;
+            depth += dist_rate;
+            self.recalculate(depth, 1, gas);
+        }
+        self.depth = *target_depth;
+    }
+
+    /// Replace the loading, e.g. with one saved from an earlier run; takes
+    /// effect with the next `start_dive`, which raises anything below
+    /// surface saturation
+    pub fn load_tissues(&mut self, compartments: impl IntoIterator<Item = Compartment>) {
+        for (comp, loaded) in self.tissues.compartments.iter_mut().zip(compartments) {
+            *comp = loaded;
+        }
+    }
+
+    /// Begin another dive from the loading carried over: the GF slope is
+    /// anchored afresh at this dive's first ceiling, and compartments that
+    /// off-gassed below the saturated start are raised back to it, so the
+    /// residual loading can only add to the plan
+    pub fn start_dive(&mut self) {
+        self.gf_low_depth = None;
+        let saturated = Tissues::new(self.config.surface_pressure);
+        for (comp, start) in self
+            .tissues
+            .compartments
+            .iter_mut()
+            .zip(saturated.compartments.iter())
+        {
+            comp.n2 = comp.n2.max(start.n2);
+            comp.he = comp.he.max(start.he);
+        }
+        let gas = self.gas;
+        self.recalculate(self.depth, 0, &gas);
+    }
+
+    /// Minutes at the current depth and gas before deco is required, up to
+    /// 98; `Minutes::MAX` beyond
+    pub fn ndl(&self) -> Minutes {
+        let mut sim = self.clone();
+        for i in 0..NDL_CUT_OFF_MIN {
+            sim.step(&self.depth, &60, &self.gas);
+            if sim.in_deco() {
+                return i;
+            }
+        }
+        Minutes::MAX
+    }
+
+    pub fn ceiling(&self) -> Depth {
+        let leading = self.min_tolerable[self.leading_index()];
+        let ceiling = (leading - self.config.surface_pressure as f64 / 1000.) * 10.;
+        ceiling.max(0.0)
+    }
+
+    pub fn in_deco(&self) -> bool {
+        self.ceiling() > 0.
+    }
+
+    pub fn supersaturation(&self) -> Supersaturation {
+        self.tissues.supersaturation(self.depth)
+    }
+
+    pub fn depth(&self) -> Depth {
+        self.depth
+    }
+
+    pub fn time(&self) -> Seconds {
+        self.time
+    }
+
+    pub fn gas(&self) -> Gas {
+        self.gas
+    }
+
+    pub fn surface_pressure_mbar(&self) -> u16 {
+        self.config.surface_pressure
+    }
+
+    /// Load the compartments, then update the tolerated pressures
+    fn recalculate(&mut self, depth: Depth, seconds: Seconds, gas: &Gas) {
+        self.tissues.step(depth, seconds, gas);
+        for i in 0..COMPARTMENT_COUNT {
+            self.min_tolerable[i] = self.tissues.min_tolerable_bar(i, self.config.gf.1);
+        }
+        let max_gf = self.max_gf(depth);
+        let leading = self.leading_index();
+        self.min_tolerable[leading] = self.tissues.min_tolerable_bar(leading, max_gf);
+    }
+
+    /// First compartment with the highest tolerated pressure
+    fn leading_index(&self) -> usize {
+        (1..COMPARTMENT_COUNT).fold(0, |leading, i| {
+            if self.min_tolerable[i] > self.min_tolerable[leading] {
+                i
+            } else {
+                leading
+            }
+        })
+    }
+
+    /// Gradient factor (percent) allowed at `depth`: gf_high out of deco,
+    /// otherwise gf_low at and below the anchor, rising linearly to gf_high
+    /// at the surface
+    fn max_gf(&mut self, depth: Depth) -> u8 {
+        let (gf_low, gf_high) = self.config.gf;
+        if !self.in_deco() {
+            return gf_high;
+        }
+
+        let gf_low_depth = *self
+            .gf_low_depth
+            .get_or_insert_with(|| self.tissues.gf_low_depth(self.depth, gf_low));
+        if depth > gf_low_depth {
+            return gf_low;
+        }
+
+        // Truncated to whole percent, as dive_deco does
+        (gf_high as f64 - ((gf_high - gf_low) as f64 / gf_low_depth) * depth) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::water::Water;
+    use dive_deco::{BuehlmannModel, DecoModel};
+
+    /// Steps both models through the same profile, comparing after each step
+    fn assert_matches_dive_deco(config: BuehlmannConfig, profile: &[(Depth, Seconds, Gas)]) {
+        let mut reference = BuehlmannModel::new(config);
+        let mut model = TrackedModel::new(config);
+        for (depth, seconds, gas) in profile {
+            reference.step_travel_with_rate(depth, &9.0, gas);
+            model.step_travel_with_rate(depth, &9.0, gas);
+            reference.step(depth, seconds, gas);
+            model.step(depth, seconds, gas);
+            assert_eq!(model.ceiling(), reference.ceiling());
+            assert_eq!(model.ndl(), reference.ndl());
+            assert_eq!(model.supersaturation(), reference.supersaturation());
+            assert_eq!(model.time(), reference.dive_state().time);
+        }
+    }
+
+    #[test]
+    fn matches_dive_deco_on_air() {
+        let air = Gas::air();
+        assert_matches_dive_deco(
+            BuehlmannConfig::new().gradient_factors(30, 70),
+            &[
+                (40., 25 * 60, air),
+                (21., 120, air),
+                (9., 300, air),
+                (3., 600, air),
+            ],
+        );
+    }
+
+    #[test]
+    fn matches_dive_deco_on_trimix() {
+        let tmx = Gas::new(0.18, 0.45);
+        let ean50 = Gas::new(0.5, 0.);
+        assert_matches_dive_deco(
+            BuehlmannConfig::new().gradient_factors(40, 85),
+            &[
+                (60., 20 * 60, tmx),
+                (21., 60, ean50),
+                (12., 240, ean50),
+                (6., 900, ean50),
+            ],
+        );
+    }
+
+    #[test]
+    fn matches_dive_deco_at_altitude() {
+        let air = Gas::air();
+        assert_matches_dive_deco(
+            BuehlmannConfig::new()
+                .gradient_factors(30, 70)
+                .surface_pressure(800),
+            &[(30., 25 * 60, air), (9., 240, air), (3., 900, air)],
+        );
+    }
+
+    #[test]
+    fn matches_dive_deco_at_fresh_water_depths() {
+        // The model is fed the seawater equivalent of each fresh water depth
+        let fresh = |depth| Water::Fresh.seawater_depth(depth);
+        let ean32 = Gas::new(0.32, 0.);
+        assert_matches_dive_deco(
+            BuehlmannConfig::new().gradient_factors(40, 85),
+            &[
+                (fresh(35.), 30 * 60, ean32),
+                (fresh(9.), 180, ean32),
+                (fresh(3.), 600, ean32),
+            ],
+        );
+    }
+
+    /// Without `start_dive`, which departs from dive_deco on purpose, a
+    /// repetitive series loads and clears the tissues exactly as dive_deco
+    #[test]
+    fn matches_dive_deco_over_a_surface_interval() {
+        let air = Gas::air();
+        let ean32 = Gas::new(0.32, 0.);
+        assert_matches_dive_deco(
+            BuehlmannConfig::new().gradient_factors(30, 70),
+            &[
+                (40., 25 * 60, air),
+                (6., 600, air),
+                (3., 900, air),
+                (0., 60 * 60, air),
+                (30., 30 * 60, ean32),
+                (3., 600, ean32),
+            ],
+        );
+    }
+
+    #[test]
+    fn start_dive_keeps_no_less_than_surface_saturation() {
+        let mut model = TrackedModel::new(BuehlmannConfig::new().gradient_factors(30, 70));
+        model.step(&45., &(20 * 60), &Gas::air());
+        model.step_travel_with_rate(&0., &9., &Gas::air());
+        model.step(&0., &(600 * 60), &Gas::air());
+        // Off-gassing at the surface ends below the 0.79 bar start
+        assert!(model.tissues.compartments[0].n2 < 0.79);
+        model.start_dive();
+        assert!(model
+            .tissues
+            .compartments
+            .iter()
+            .all(|comp| comp.n2 >= 0.79));
+    }
+
+    #[test]
+    fn start_dive_resets_the_gf_low_anchor() {
+        let config = BuehlmannConfig::new().gradient_factors(30, 70);
+        let air = Gas::air();
+        let fresh_ceiling = {
+            let mut fresh = TrackedModel::new(config);
+            fresh.step(&30., &(30 * 60), &air);
+            fresh.ceiling()
+        };
+        let mut model = TrackedModel::new(config);
+        model.step(&45., &(20 * 60), &air);
+        assert!(model.gf_low_depth.is_some());
+        model.step(&0., &(24 * 60 * 60), &air);
+        model.start_dive();
+        assert_eq!(model.gf_low_depth, None);
+        model.step(&30., &(30 * 60), &air);
+        assert!(model.ceiling() >= fresh_ceiling);
+    }
+
+    #[test]
+    fn starts_saturated_at_surface() {
+        let model = TrackedModel::new(BuehlmannConfig::new());
+        assert_eq!(model.ceiling(), 0.);
+        assert!(model
+            .tissues
+            .compartments
+            .iter()
+            .all(|comp| comp.n2 == 0.79));
+    }
+}
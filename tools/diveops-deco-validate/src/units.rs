@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const METERS_PER_FOOT: f64 = 0.3048;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+pub fn feet_to_m(feet: f64) -> f64 {
+    feet * METERS_PER_FOOT
+}
+
+pub fn m_to_feet(meters: f64) -> f64 {
+    meters / METERS_PER_FOOT
+}
+
+/// A depth in meters as messages print it: in `units`, to 0.1
+pub fn depth_label(units: Units, depth_m: f64) -> String {
+    let (depth, unit) = match units {
+        Units::Metric => (depth_m, "m"),
+        Units::Imperial => (m_to_feet(depth_m), "ft"),
+    };
+    format!("{}{unit}", (depth * 10.0).round() / 10.0)
+}
+
+/// Rewrite every depth field (`*_m`) of a serialized output as feet (`*_ft`)
+pub fn to_imperial(value: &mut Value) {
+    add_feet(value, false);
+}
+
+/// Add a feet (`*_ft`) copy next to every depth field (`*_m`) of a
+/// serialized output
+pub fn to_dual(value: &mut Value) {
+    add_feet(value, true);
+}
+
+fn add_feet(value: &mut Value, keep_meters: bool) {
+    match value {
+        // Rebuilt in order, so each feet field takes its meters field's place
+        Value::Object(map) => {
+            let fields = std::mem::take(map);
+            for (key, mut v) in fields {
+                add_feet(&mut v, keep_meters);
+                let Some(stem) = key.strip_suffix("_m") else {
+                    map.insert(key, v);
+                    continue;
+                };
+                let feet_key = format!("{stem}_ft");
+                let feet = v.as_f64().map(|meters| Value::from(m_to_feet(meters)));
+                if keep_meters {
+                    map.insert(key, v);
+                    if let Some(feet) = feet {
+                        map.insert(feet_key, feet);
+                    }
+                } else {
+                    map.insert(feet_key, feet.unwrap_or(v));
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| add_feet(v, keep_meters)),
+        _ => {}
+    }
+}
@@ -0,0 +1,93 @@
+//! Plan warnings with stable codes and severities.
+
+use serde::Serialize;
+
+/// How much a warning matters to the dive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Worth knowing; the plan is unaffected
+    Info,
+    /// The plan works but is outside common practice
+    Caution,
+    /// The plan as given is unsafe
+    Danger,
+}
+
+/// Every kind of warning; serialized as its stable snake_case name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningCode {
+    HighAltitude,
+    ConservatismIgnored,
+    UnusualLastStop,
+    UnsafeGradientFactors,
+    NoDescent,
+    SkippedStop,
+    CeilingViolation,
+    ModExceeded,
+    HypoxicGas,
+    EndExceeded,
+    GasDensityExceeded,
+    CnsExceeded,
+    NoSafetyStop,
+    NoFlyCap,
+    DeepGasSwitch,
+    InsufficientGas,
+    YoYoProfile,
+    NoDecoGas,
+    AscentRateExceeded,
+}
+
+impl WarningCode {
+    pub fn severity(self) -> Severity {
+        match self {
+            WarningCode::ConservatismIgnored
+            | WarningCode::UnusualLastStop
+            | WarningCode::NoSafetyStop
+            | WarningCode::NoFlyCap
+            | WarningCode::NoDecoGas => Severity::Info,
+            WarningCode::HighAltitude
+            | WarningCode::UnsafeGradientFactors
+            | WarningCode::NoDescent
+            | WarningCode::EndExceeded
+            | WarningCode::GasDensityExceeded
+            | WarningCode::CnsExceeded
+            | WarningCode::YoYoProfile
+            | WarningCode::AscentRateExceeded => Severity::Caution,
+            WarningCode::SkippedStop
+            | WarningCode::CeilingViolation
+            | WarningCode::ModExceeded
+            | WarningCode::HypoxicGas
+            | WarningCode::DeepGasSwitch
+            | WarningCode::InsufficientGas => Severity::Danger,
+        }
+    }
+}
+
+/// A condition worth reporting that doesn't stop the plan
+#[derive(Debug, Clone, Serialize)]
+pub struct Warning {
+    pub code: WarningCode,
+    pub message: String,
+    pub severity: Severity,
+    /// Zero-based index into the reported dive's `segments`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segment_index: Option<usize>,
+}
+
+impl Warning {
+    pub fn new(code: WarningCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            severity: code.severity(),
+            segment_index: None,
+        }
+    }
+
+    pub fn at_segment(mut self, index: usize) -> Self {
+        self.segment_index = Some(index);
+        self
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+/// Standard gravity in m/s²
+const STANDARD_GRAVITY: f64 = 9.80665;
+/// Density the model's 10m-per-bar depth convention stands for (about
+/// 1019.7 kg/m³); every conversion and the reported salt density use it
+const SALT_DENSITY_KG_PER_M3: f64 = 100_000.0 / (STANDARD_GRAVITY * 10.0);
+const FRESH_DENSITY_KG_PER_M3: f64 = 1000.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Water {
+    #[default]
+    Salt,
+    Fresh,
+}
+
+impl Water {
+    pub fn density_kg_per_m3(self) -> f64 {
+        match self {
+            Water::Salt => SALT_DENSITY_KG_PER_M3,
+            Water::Fresh => FRESH_DENSITY_KG_PER_M3,
+        }
+    }
+
+    /// Seawater depth with the same pressure as `depth_m` in this water
+    pub fn seawater_depth(self, depth_m: f64) -> f64 {
+        match self {
+            // Exact, without a round trip through the density ratio
+            Water::Salt => depth_m,
+            Water::Fresh => depth_m * self.density_kg_per_m3() / SALT_DENSITY_KG_PER_M3,
+        }
+    }
+
+    /// Depth in this water with the same pressure as a seawater depth
+    pub fn actual_depth(self, seawater_depth_m: f64) -> f64 {
+        match self {
+            Water::Salt => seawater_depth_m,
+            Water::Fresh => seawater_depth_m * SALT_DENSITY_KG_PER_M3 / self.density_kg_per_m3(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn salt_density_is_ten_meters_per_bar() {
+        let pascal_per_10m = Water::Salt.density_kg_per_m3() * STANDARD_GRAVITY * 10.0;
+        assert!((pascal_per_10m - 100_000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn salt_depths_pass_through_unchanged() {
+        assert_eq!(Water::Salt.seawater_depth(12.0), 12.0);
+        assert_eq!(Water::Salt.actual_depth(12.0), 12.0);
+    }
+
+    #[test]
+    fn fresh_depths_round_trip() {
+        let seawater = Water::Fresh.seawater_depth(30.0);
+        assert!(seawater < 30.0);
+        assert!((seawater - 30.0 * 1000.0 / SALT_DENSITY_KG_PER_M3).abs() < 1e-12);
+        assert!((Water::Fresh.actual_depth(seawater) - 30.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,998 @@
+use serde_json::Value;
+use sha2::Digest;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Run the binary with `args`, feeding `input` on stdin
+fn run(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_diveops-deco-validate"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start binary");
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_bytes())
+        .expect("failed to write stdin");
+    child.wait_with_output().expect("failed to wait on binary")
+}
+
+/// Single-segment air payload at the given raw JSON depth and duration
+fn payload(depth: &str, duration: &str) -> String {
+    format!(
+        r#"{{"segments":[{{"depth_m":{depth},"duration_min":{duration}}}],"gas":{{"o2":0.21,"he":0.0}},"gf_low":0.3,"gf_high":0.7}}"#
+    )
+}
+
+#[test]
+fn rejects_negative_depth() {
+    let out = run(&[], &payload("-5", "20"));
+    assert_eq!(out.status.code(), Some(22));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("depth_m"));
+}
+
+#[test]
+fn rejects_zero_duration() {
+    let out = run(&[], &payload("30", "0"));
+    assert_eq!(out.status.code(), Some(22));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("duration_min"));
+}
+
+#[test]
+fn rejects_nan_depth() {
+    // JSON has no NaN; the literal and out-of-range numbers fail parsing
+    // instead of reaching the model
+    for depth in ["NaN", "1e400"] {
+        let out = run(&[], &payload(depth, "20"));
+        assert_eq!(out.status.code(), Some(3), "depth {depth}");
+    }
+}
+
+#[test]
+fn invalid_segment_reported_as_json_error() {
+    let out = run(&["--json-errors"], &payload("30", "-1"));
+    assert_eq!(out.status.code(), Some(0));
+    let value: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(value["error_code"], "invalid_segment");
+}
+
+#[test]
+fn identical_input_gives_identical_output() {
+    let input = r#"{"segments":[{"depth_m":45,"duration_min":25}],"gas":{"o2":0.21,"he":0.35},"deco_gases":[{"o2":0.5,"he":0.0},{"o2":1.0,"he":0.0}],"gf_low":0.3,"gf_high":0.7}"#;
+    let first = run(&[], input);
+    let second = run(&[], input);
+    assert_eq!(first.status.code(), Some(0));
+    assert_eq!(first.stdout, second.stdout);
+
+    let value: serde_json::Value = serde_json::from_slice(&first.stdout).unwrap();
+    let depths: Vec<f64> = value["stops"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|stop| stop["depth_m"].as_f64().unwrap())
+        .collect();
+    assert!(depths.len() > 1);
+    assert!(depths.windows(2).all(|pair| pair[0] >= pair[1]));
+}
+
+#[test]
+fn validation_failures_map_to_their_codes() {
+    let air = r#""gas":"air","gf_low":0.3,"gf_high":0.7"#;
+    let seg = r#""segments":[{"depth_m":30,"duration_min":20}]"#;
+    let cases: Vec<(String, i32, &str)> = vec![
+        ("{".to_string(), 3, "invalid_json"),
+        (format!(r#"{{"segments":[],{air}}}"#), 4, "no_segments"),
+        (
+            format!(r#"{{{seg},"gas":{{"o2":1.2,"he":0}},"gf_low":0.3,"gf_high":0.7}}"#),
+            5,
+            "invalid_gas_fractions",
+        ),
+        (
+            format!(r#"{{{seg},"gas":{{"o2":0.6,"he":0.6}},"gf_low":0.3,"gf_high":0.7}}"#),
+            6,
+            "gas_fractions_exceed_one",
+        ),
+        (
+            format!(r#"{{{seg},"gas":{{"o2":0.32,"he":0,"n2":0.6}},"gf_low":0.3,"gf_high":0.7}}"#),
+            29,
+            "gas_fractions_do_not_sum_to_one",
+        ),
+        (
+            format!(r#"{{{seg},{air},"last_stop_m":12}}"#),
+            8,
+            "invalid_last_stop",
+        ),
+        (
+            format!(r#"{{{seg},{air},"sac_l_per_min":-1}}"#),
+            9,
+            "invalid_sac",
+        ),
+        (
+            format!(r#"{{{seg},{air},"ascent_rate_m_per_min":0}}"#),
+            11,
+            "invalid_rate",
+        ),
+        (
+            format!(r#"{{{seg},{air},"dives":[{{{seg}}}]}}"#),
+            12,
+            "segments_and_dives",
+        ),
+        (
+            format!(r#"{{"dives":[{{{seg},"surface_interval_min":-5}}],{air}}}"#),
+            13,
+            "invalid_surface_interval",
+        ),
+        (
+            format!(r#"{{{seg},{air},"max_po2":0}}"#),
+            15,
+            "invalid_po2_limit",
+        ),
+        (
+            format!(r#"{{{seg},{air},"algorithm":"vpm-b"}}"#),
+            17,
+            "unsupported_algorithm",
+        ),
+        (
+            format!(r#"{{{seg},{air},"altitude_m":9000}}"#),
+            18,
+            "invalid_altitude",
+        ),
+        (
+            format!(r#"{{{seg},"gas":"air","gf_low":0.8,"gf_high":0.3}}"#),
+            19,
+            "invalid_gradient_factors",
+        ),
+        (
+            format!(r#"{{{seg},{air},"schema_version":99}}"#),
+            21,
+            "unsupported_schema_version",
+        ),
+        (payload("30", "0"), 22, "invalid_segment"),
+        (
+            format!(r#"{{{seg},{air},"ceiling_tolerance_m":-1}}"#),
+            23,
+            "invalid_ceiling_tolerance",
+        ),
+        (
+            format!(r#"{{{seg},{air},"water_temp_c":80}}"#),
+            35,
+            "invalid_water_temp",
+        ),
+        (
+            format!(r#"{{{seg},{air},"yo_yo_delta_m":0}}"#),
+            37,
+            "invalid_yo_yo_delta",
+        ),
+    ];
+
+    for (input, exit_code, error_code) in cases {
+        let out = run(&[], &input);
+        assert_eq!(out.status.code(), Some(exit_code), "{input}");
+
+        let out = run(&["--json-errors"], &input);
+        let value: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!(value["error_code"], error_code, "{input}");
+    }
+}
+
+#[test]
+fn strict_failures_map_to_their_codes() {
+    let input =
+        r#"{"segments":[{"depth_m":30,"duration_min":20}],"gas":"air","gf_low":0.3,"gf_high":1.0}"#;
+    assert_eq!(run(&["--strict"], input).status.code(), Some(20));
+
+    // Starting at depth is only a warning without --strict
+    assert_eq!(
+        run(&["--strict"], &payload("30", "20")).status.code(),
+        Some(31)
+    );
+    assert_eq!(run(&[], &payload("30", "20")).status.code(), Some(0));
+}
+
+#[test]
+fn missing_input_file_maps_to_its_code() {
+    let out = run(&["/nonexistent/input.json"], "");
+    assert_eq!(out.status.code(), Some(10));
+}
+
+#[test]
+fn strict_warnings_print_output_with_dedicated_code() {
+    let input = r#"{"segments":[{"depth_m":40,"duration_min":20}],"gas":"air","gf_low":0.3,"gf_high":0.7,"descent_rate_m_per_min":18}"#;
+    let out = run(&["--strict"], input);
+    assert_eq!(out.status.code(), Some(28));
+    let value: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert!(!value["warnings"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn strict_safety_warnings_still_print_the_plan() {
+    let cases = [
+        (
+            r#""depth_m":60,"duration_min":10"#,
+            r#""air""#,
+            "mod_exceeded",
+        ),
+        (
+            r#""depth_m":0.5,"duration_min":10"#,
+            r#""tmx10/70""#,
+            "hypoxic_gas",
+        ),
+        (
+            r#""depth_m":30,"duration_min":20"#,
+            r#""air","sac_l_per_min":20,"cylinder_l":10,"fill_bar":100"#,
+            "insufficient_gas",
+        ),
+    ];
+    for (segment, gas, warning) in cases {
+        let input = format!(
+            r#"{{"segments":[{{{segment}}}],"gas":{gas},"gf_low":0.3,"gf_high":0.7,"descent_rate_m_per_min":18}}"#
+        );
+        for args in [&["--strict"][..], &["--strict", "--all-errors"]] {
+            let out = run(args, &input);
+            assert_eq!(out.status.code(), Some(28), "{args:?} {input}");
+            let value: Value = serde_json::from_slice(&out.stdout).unwrap();
+            assert!(value["tts_min"].is_number(), "{input}");
+            let warnings = value["warnings"].as_array().unwrap();
+            assert!(
+                warnings.iter().any(|w| w["code"] == warning),
+                "{warnings:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn stage_bottles_use_the_breathed_mix_per_segment() {
+    let input = r#"{"segments":[
+        {"depth_m":40,"duration_min":15},
+        {"depth_m":21,"duration_min":10,"gas":"ean50"},
+        {"depth_m":30,"duration_min":10},
+        {"depth_m":6,"duration_min":5,"gas":"o2"}
+    ],"gas":"tmx21/35","deco_gases":["ean50","o2"],"gf_low":0.3,"gf_high":0.7}"#;
+    let out = run(&["--verbose"], input);
+    assert_eq!(out.status.code(), Some(0));
+    let value: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+
+    let po2: Vec<f64> = value["segment_po2"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p.as_f64().unwrap())
+        .collect();
+    let expected = [
+        0.21 * (1.013 + 4.0),
+        0.5 * (1.013 + 2.1),
+        0.21 * (1.013 + 3.0),
+        1.0 * (1.013 + 0.6),
+    ];
+    assert_eq!(po2.len(), expected.len());
+    for (actual, expected) in po2.iter().zip(expected) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+    // Back on trimix at 30m, the stage's 1.56 bar must not be reported
+    assert!(value["warnings"]
+        .as_array()
+        .is_none_or(|w| w.iter().all(|w| w["code"] != "mod_exceeded")));
+}
+
+#[test]
+fn declared_n2_is_accepted_within_rounding() {
+    // 18/45/36 from rounded percentages sums to 0.99
+    let input = r#"{"segments":[{"depth_m":40,"duration_min":20}],"gas":{"o2":0.18,"he":0.45,"n2":0.36},"gf_low":0.3,"gf_high":0.7}"#;
+    let out = run(&[], input);
+    assert_eq!(out.status.code(), Some(0));
+    let value: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    let n2 = value["gas"]["n2"].as_f64().unwrap();
+    assert!((n2 - 0.37).abs() < 1e-9, "{n2}");
+}
+
+#[test]
+fn empty_input_has_its_own_code() {
+    for input in ["", "  \n\t"] {
+        let out = run(&[], input);
+        assert_eq!(out.status.code(), Some(30), "{input:?}");
+        assert!(String::from_utf8_lossy(&out.stderr).contains("empty input"));
+    }
+}
+
+#[test]
+fn warning_codes_are_stable() {
+    let input = r#"{"segments":[{"depth_m":45,"duration_min":20}],"gas":"ean32","gf_low":0.3,"gf_high":0.7}"#;
+    let out = run(&[], input);
+    assert_eq!(out.status.code(), Some(0));
+    let value: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    let warnings = value["warnings"].as_array().unwrap();
+    let find = |code: &str| {
+        warnings
+            .iter()
+            .find(|w| w["code"] == code)
+            .unwrap_or_else(|| panic!("no {code} warning in {warnings:?}"))
+    };
+
+    let no_descent = find("no_descent");
+    assert_eq!(no_descent["severity"], "caution");
+    assert_eq!(no_descent["segment_index"], 0);
+    assert_eq!(find("end_exceeded")["severity"], "caution");
+    assert_eq!(find("mod_exceeded")["severity"], "danger");
+    assert_eq!(find("gas_density_exceeded")["segment_index"], 0);
+    assert!(find("cns_exceeded").get("segment_index").is_none());
+
+    let out = run(&["--legacy-warnings"], input);
+    let value: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    let legacy = value["warnings"].as_array().unwrap();
+    assert_eq!(legacy.len(), warnings.len());
+    for (string, structured) in legacy.iter().zip(warnings) {
+        assert_eq!(string.as_str(), structured["message"].as_str());
+    }
+}
+
+#[test]
+fn twelve_hour_segment_matches_hourly_steps() {
+    let plan = |segments: &str| {
+        let input = format!(
+            r#"{{"segments":[{segments}],"gas":"air","gf_low":0.3,"gf_high":0.7,"descent_rate_m_per_min":18}}"#
+        );
+        let out = run(&["--tissues"], &input);
+        assert_eq!(out.status.code(), Some(0), "{input}");
+        serde_json::from_slice::<serde_json::Value>(&out.stdout).unwrap()
+    };
+    let single = plan(r#"{"depth_m":10,"duration_min":720}"#);
+    let hourly = plan(&[r#"{"depth_m":10,"duration_min":60}"#; 12].join(","));
+
+    assert_eq!(single["tts_min"], hourly["tts_min"]);
+    assert!(single["runtime_min"].as_f64().unwrap() > 720.0);
+    let loading = |value: &serde_json::Value| -> Vec<f64> {
+        value["tissue_pressures"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["n2_bar"].as_f64().unwrap())
+            .collect()
+    };
+    for (a, b) in loading(&single).iter().zip(loading(&hourly)) {
+        assert!((a - b).abs() < 1e-9, "{a} != {b}");
+    }
+
+    let too_long = payload("10", "50000");
+    assert_eq!(run(&[], &too_long).status.code(), Some(22));
+}
+
+#[test]
+fn ndjson_streams_each_result_before_the_input_ends() {
+    use std::io::{BufRead, BufReader};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_diveops-deco-validate"))
+        .arg("--ndjson")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to start binary");
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+    // Each result must arrive while stdin is still open
+    for depth in 10..60 {
+        writeln!(stdin, "{}", payload(&depth.to_string(), "10")).unwrap();
+        stdin.flush().unwrap();
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["max_depth_m"], depth as f64, "{line}");
+    }
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+}
+
+/// Check `value` against the subset of JSON Schema that `--schema` uses,
+/// returning the path and reason of the first mismatch
+fn check_schema(value: &Value, schema: &Value, root: &Value, path: &str) -> Result<(), String> {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.trim_start_matches("#/$defs/");
+        return check_schema(value, &root["$defs"][name], root, path);
+    }
+    if let Some(options) = schema["anyOf"].as_array() {
+        return match options
+            .iter()
+            .any(|s| check_schema(value, s, root, path).is_ok())
+        {
+            true => Ok(()),
+            false => Err(format!("{path}: matches no anyOf branch")),
+        };
+    }
+    if let Some(allowed) = schema["enum"].as_array() {
+        if !allowed.contains(value) {
+            return Err(format!("{path}: {value} is not one of {allowed:?}"));
+        }
+    }
+    if !schema["const"].is_null() && schema["const"] != *value {
+        return Err(format!("{path}: {value} is not {}", schema["const"]));
+    }
+    let types: Vec<&str> = match &schema["type"] {
+        Value::String(t) => vec![t],
+        Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    };
+    let type_matches = |t: &&str| match *t {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_u64() || value.is_i64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        other => panic!("schema type {other} not handled"),
+    };
+    if !types.is_empty() && !types.iter().any(type_matches) {
+        return Err(format!("{path}: {value} is not {types:?}"));
+    }
+    if let Some(items) = value.as_array() {
+        for (i, item) in items.iter().enumerate() {
+            check_schema(item, &schema["items"], root, &format!("{path}[{i}]"))?;
+        }
+    }
+    if let Some(fields) = value.as_object() {
+        for required in schema["required"].as_array().into_iter().flatten() {
+            let required = required.as_str().unwrap();
+            if !fields.contains_key(required) {
+                return Err(format!("{path}: missing {required}"));
+            }
+        }
+        for (key, field) in fields {
+            let field_path = format!("{path}.{key}");
+            let pattern = schema["patternProperties"]
+                .as_object()
+                .and_then(|patterns| {
+                    patterns
+                        .iter()
+                        .find(|(p, _)| key.ends_with(p.trim_end_matches('$')))
+                });
+            match (&schema["properties"][key], pattern) {
+                (Value::Null, Some((_, s))) => check_schema(field, s, root, &field_path)?,
+                (Value::Null, None) => return Err(format!("{field_path}: not in the schema")),
+                (s, _) => check_schema(field, s, root, &field_path)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn output_matches_the_published_schema() {
+    let schema: Value = serde_json::from_slice(&run(&["--schema"], "").stdout).unwrap();
+    let deco = r#"{"dives":[{"segments":[{"depth_m":20,"duration_min":30}]},{"surface_interval_min":60,"segments":[{"pressure_bar":5.0,"duration_min":25},{"depth_m":20,"duration_min":5,"gas":"ean50"}]}],"gas":"air","deco_gases":["ean50"],"gf_low":0.3,"gf_high":0.7,"sac_l_per_min":20,"stress_sac_l_per_min":30,"cylinder_l":24,"fill_bar":200,"rounded_stops":true,"compare_gf":{"gf_low":0.5,"gf_high":0.9},"descent_rate_m_per_min":18}"#;
+    let no_deco = payload("18", "20");
+    let imperial = no_deco.replace(r#""depth_m":18"#, r#""depth_m":60,"units":"imperial""#);
+    let cases: [(&[&str], &str); 5] = [
+        (
+            &[
+                "--tissues",
+                "--trace",
+                "--verbose",
+                "--all-stages",
+                "--solve-bottom-time",
+            ],
+            deco,
+        ),
+        (&["--legacy-warnings", "--dual-units"], deco),
+        (&["--verbose", "--solve-max-depth"], &no_deco),
+        (&["--tissues", "--all-stages"], &imperial),
+        (&["--dual-units", "--precision", "2"], &imperial),
+    ];
+    for (args, input) in cases {
+        let out = run(args, input);
+        assert_eq!(out.status.code(), Some(0), "{args:?}: {input}");
+        let value: Value = serde_json::from_slice(&out.stdout).unwrap();
+        if let Err(mismatch) = check_schema(&value, &schema, &schema, "$") {
+            panic!("{args:?}: {mismatch}");
+        }
+    }
+
+    // The pressure reading is the final dive's first segment
+    let value: Value = serde_json::from_slice(&run(&[], deco).stdout).unwrap();
+    assert_eq!(value["pressure_depths"][0]["segment_index"], 0);
+}
+
+#[test]
+fn no_deco_shortcut_matches_the_deco_simulation() {
+    let inputs = [
+        payload("18", "20"),
+        payload("0", "10"),
+        payload("30.7", "5"),
+        r#"{"segments":[{"depth_m":25,"duration_min":10},{"depth_m":12,"duration_min":20,"gas":"ean50"}],"gas":"ean32","deco_gases":["ean50"],"gf_low":0.4,"gf_high":0.85,"descent_rate_m_per_min":18,"ascent_rate_m_per_min":9,"sac_l_per_min":18,"cylinder_l":12,"fill_bar":200,"last_stop_m":6}"#.to_string(),
+    ];
+    for input in &inputs {
+        let shortcut = run(&["--all-stages", "--tissues"], input);
+        let simulated = run(&["--all-stages", "--tissues", "--force-deco"], input);
+        assert_eq!(shortcut.status.code(), Some(0), "{input}");
+        let value: Value = serde_json::from_slice(&shortcut.stdout).unwrap();
+        assert_eq!(value["deco_required"], false, "{input}");
+        assert_eq!(shortcut.stdout, simulated.stdout, "{input}");
+    }
+}
+
+#[test]
+fn no_deco_tts_is_the_direct_ascent() {
+    for args in [&[][..], &["--force-deco"]] {
+        let value: Value = serde_json::from_slice(&run(args, &payload("18", "20")).stdout).unwrap();
+        assert_eq!(value["deco_required"], false, "{args:?}");
+        assert_eq!(value["stops"], serde_json::json!([]), "{args:?}");
+        // 18m at 9 m/min
+        assert_eq!(value["tts_min"], 2.0, "{args:?}");
+        assert_eq!(value["total_stop_min"], 0.0, "{args:?}");
+    }
+}
+
+#[test]
+fn yaml_input_plans_like_json() {
+    let yaml = "\
+segments:
+  - depth_m: 30
+    duration_min: 20
+gas: air
+deco_gases: [ean50]
+gf_low: 0.3
+gf_high: 0.7
+";
+    let json = payload("30", "20").replace(
+        r#""gas":{"o2":0.21,"he":0.0}"#,
+        r#""gas":"air","deco_gases":["ean50"]"#,
+    );
+    let mut from_yaml: Value = serde_json::from_slice(&run(&["--yaml"], yaml).stdout).unwrap();
+    let mut from_json: Value = serde_json::from_slice(&run(&[], &json).stdout).unwrap();
+
+    // The hash covers the YAML as given
+    let digest = sha2::Sha256::digest(yaml.as_bytes());
+    assert_eq!(
+        from_yaml["input_hash"],
+        format!("sha256:{}", hex::encode(digest))
+    );
+    // Otherwise the plans match; output_hash follows input_hash
+    for value in [&mut from_yaml, &mut from_json] {
+        let object = value.as_object_mut().unwrap();
+        object.remove("input_hash");
+        object.remove("output_hash");
+    }
+    assert_eq!(from_yaml, from_json);
+
+    let out = run(&["--yaml", "--json-errors"], "segments: [");
+    let value: Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(value["error_code"], "invalid_yaml");
+    assert_eq!(run(&["--yaml"], "segments: [").status.code(), Some(40));
+}
+
+#[test]
+fn repetitive_dive_needs_no_less_deco_than_a_fresh_one() {
+    let fresh: Value = serde_json::from_slice(&run(&[], &payload("30", "15")).stdout).unwrap();
+    for interval in ["600", "60"] {
+        let input = format!(
+            r#"{{"dives":[{{"segments":[{{"depth_m":45,"duration_min":20}}],"surface_interval_min":0}},{{"segments":[{{"depth_m":30,"duration_min":15}}],"surface_interval_min":{interval}}}],"gas":"air","gf_low":0.3,"gf_high":0.7}}"#
+        );
+        let repetitive: Value = serde_json::from_slice(&run(&[], &input).stdout).unwrap();
+        for field in ["tts_min", "ceiling_m"] {
+            assert!(
+                repetitive[field].as_f64().unwrap() >= fresh[field].as_f64().unwrap(),
+                "{field} after {interval} min: {} vs fresh {}",
+                repetitive[field],
+                fresh[field]
+            );
+        }
+        let stops = |value: &Value| value["stops"].as_array().unwrap().len();
+        assert!(
+            stops(&repetitive) >= stops(&fresh),
+            "stops after {interval} min"
+        );
+    }
+}
+
+#[test]
+fn initial_tissues_set_the_starting_loading() {
+    let with_tissues = |n2: f64| {
+        let tissues = vec![format!(r#"{{"n2_bar":{n2},"he_bar":0}}"#); 16].join(",");
+        payload("30", "15").replace(
+            r#""gf_high":0.7}"#,
+            &format!(r#""gf_high":0.7,"initial_tissues":[{tissues}]}}"#),
+        )
+    };
+    let plan = |input: &str| {
+        let out = run(&["--tissues"], input);
+        assert_eq!(out.status.code(), Some(0), "{input}");
+        let mut value: Value = serde_json::from_slice(&out.stdout).unwrap();
+        let object = value.as_object_mut().unwrap();
+        object.remove("input_hash");
+        object.remove("output_hash");
+        value
+    };
+    let fresh = plan(&payload("30", "15"));
+
+    // Surface saturation is the default start, and nothing starts below it
+    assert_eq!(plan(&with_tissues(0.79)), fresh);
+    assert_eq!(plan(&with_tissues(0.5)), fresh);
+
+    let loaded = plan(&with_tissues(1.2));
+    assert!(loaded["tts_min"].as_f64().unwrap() > fresh["tts_min"].as_f64().unwrap());
+    assert!(
+        loaded["tissue_pressures"][15]["n2_bar"].as_f64().unwrap()
+            > fresh["tissue_pressures"][15]["n2_bar"].as_f64().unwrap()
+    );
+
+    let out = run(&[], &with_tissues(-1.0));
+    assert_eq!(out.status.code(), Some(38));
+}
+
+#[test]
+fn deco_gases_switch_within_max_deco_po2() {
+    let input = payload("40", "30").replace(
+        r#""gf_high":0.7}"#,
+        r#""gf_high":0.7,"deco_gases":["ean50"],"max_deco_po2":1.4}"#,
+    );
+    let out = run(&["--all-stages"], &input);
+    let value: Value = serde_json::from_slice(&out.stdout).unwrap();
+    let switches: Vec<f64> = value["stages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|stage| stage["stage_type"] == "gas_switch")
+        .map(|stage| stage["end_depth_m"].as_f64().unwrap())
+        .collect();
+    // EAN50's MOD at 1.4 bar is 18m; at dive_deco's 1.6 bar it would be 22m
+    assert_eq!(switches, [18.0]);
+    let codes: Vec<&Value> = value["warnings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|w| &w["code"])
+        .collect();
+    assert!(
+        !codes.contains(&&Value::from("deep_gas_switch")),
+        "{codes:?}"
+    );
+}
+
+#[test]
+fn standard_oxygen_deco_stays_within_the_cns_limit() {
+    let input = r#"{"segments":[{"depth_m":45,"duration_min":25}],"gas":"tmx21/35","deco_gases":["ean50","o2"],"gf_low":0.3,"gf_high":0.7}"#;
+    let value: Value = serde_json::from_slice(&run(&[], input).stdout).unwrap();
+    let stops = value["stops"].as_array().unwrap();
+    assert!(
+        stops.iter().any(|stop| stop["gas"]["o2"] == 1.0),
+        "{stops:?}"
+    );
+
+    // The deco counts, O2 at 6m included, but not as a limit breach
+    let cns = value["cns_percent"].as_f64().unwrap();
+    assert!((30.0..80.0).contains(&cns), "cns_percent {cns}");
+    let warnings = value["warnings"].as_array().unwrap();
+    assert!(
+        warnings.iter().all(|w| w["code"] != "cns_exceeded"),
+        "{warnings:?}"
+    );
+}
+
+#[test]
+fn min_gas_ascends_at_the_configured_rate() {
+    let min_gas = |rate: &str| {
+        let input = format!(
+            r#"{{"segments":[{{"depth_m":20,"duration_min":10}},{{"depth_m":10,"duration_min":10}}],"gas":"air","gf_low":0.3,"gf_high":0.7,"stress_sac_l_per_min":20{rate}}}"#
+        );
+        let value: Value = serde_json::from_slice(&run(&[], &input).stdout).unwrap();
+        assert_eq!(value["deco_required"], false);
+        value["min_gas_l"].as_f64().unwrap()
+    };
+    // Two divers: 20m to 10m at the ascent rate (mean 15m), then the
+    // planner's 66s direct ascent from 10m (mean 5m)
+    let expected = |rate: f64| 2.0 * (20.0 * 2.5 * (10.0 / rate) + 20.0 * 1.5 * (66.0 / 60.0));
+    assert!((min_gas("") - expected(9.0)).abs() < 1e-9);
+    assert!((min_gas(r#","ascent_rate_m_per_min":3"#) - expected(3.0)).abs() < 1e-9);
+}
+
+#[test]
+fn fresh_water_reports_every_depth_in_fresh_water() {
+    let plan = |water: &str| {
+        let input = payload("30", "27").replace(
+            r#""gf_high":0.7}"#,
+            &format!(r#""gf_high":0.7,"water":"{water}"}}"#),
+        );
+        let out = run(&["--all-stages"], &input);
+        serde_json::from_slice::<Value>(&out.stdout).unwrap()
+    };
+    let salt = plan("salt");
+    let fresh = plan("fresh");
+    let density = salt["water_density_kg_per_m3"].as_f64().unwrap();
+    assert!((density - 1019.716).abs() < 1e-3, "{density}");
+    let to_fresh = density / fresh["water_density_kg_per_m3"].as_f64().unwrap();
+
+    // Stops sit at the fresh water depths of the model's 3m increments
+    for stop in fresh["stops"].as_array().unwrap() {
+        let increments = stop["depth_m"].as_f64().unwrap() / (3.0 * to_fresh);
+        assert!((increments - increments.round()).abs() < 1e-9, "{stop}");
+    }
+    assert_eq!(fresh["stops"][0]["depth_m"], fresh["first_stop_m"]);
+    assert_eq!(fresh["schedule"][0]["depth_m"], fresh["first_stop_m"]);
+
+    // The ceiling rounds up to a whole 3m stop depth from the same value
+    let ceiling = fresh["ceiling_m"].as_f64().unwrap();
+    let rounded = fresh["ceiling_rounded_m"].as_f64().unwrap();
+    assert!(rounded >= ceiling, "{rounded} < {ceiling}");
+    assert!(rounded - ceiling < 3.0);
+    assert_eq!(rounded % 3.0, 0.0);
+    for stage in fresh["stages"].as_array().unwrap() {
+        assert!(
+            stage["start_depth_m"].as_f64().unwrap() <= 30.0 + 1e-9,
+            "{stage}"
+        );
+    }
+
+    // Air's END is the depth itself, in either water
+    assert!((fresh["max_end_m"].as_f64().unwrap() - 30.0).abs() < 1e-9);
+    assert_eq!(salt["max_end_m"], 30.0);
+
+    // A 3m safety stop is 3m in fresh water too, though shallower in seawater
+    let safety_stop_warnings = |water: &str| {
+        let input = format!(
+            r#"{{"segments":[{{"depth_m":18,"duration_min":20}},{{"depth_m":3,"duration_min":3}}],"gas":"air","gf_low":0.3,"gf_high":0.7,"descent_rate_m_per_min":18,"water":"{water}"}}"#
+        );
+        let value: Value = serde_json::from_slice(&run(&[], &input).stdout).unwrap();
+        value["warnings"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|warning| warning["code"] == "no_safety_stop")
+            .count()
+    };
+    assert_eq!(safety_stop_warnings("fresh"), 0);
+    assert_eq!(safety_stop_warnings("salt"), 0);
+}
+
+/// Every object key of a serialized output, in document order
+fn key_order(value: &Value, keys: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map {
+                keys.push(key.clone());
+                key_order(v, keys);
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|v| key_order(v, keys)),
+        _ => {}
+    }
+}
+
+#[test]
+fn imperial_output_keeps_the_metric_layout_and_units() {
+    let plan = |depth: &str, units: &str, args: &[&str]| {
+        let input = format!(
+            r#"{{"segments":[{{"depth_m":{depth},"duration_min":20}}],"gas":"air","gf_low":0.3,"gf_high":0.7,"units":"{units}"}}"#
+        );
+        serde_json::from_slice::<Value>(&run(args, &input).stdout).unwrap()
+    };
+    let keys = |value: &Value| {
+        let mut keys = Vec::new();
+        key_order(value, &mut keys);
+        keys
+    };
+    // 130ft is 39.624m
+    let metric = keys(&plan("39.624", "metric", &[]));
+    let imperial = plan("130", "imperial", &[]);
+    let feet: Vec<String> = metric
+        .iter()
+        .map(|k| match k.strip_suffix("_m") {
+            Some(stem) => format!("{stem}_ft"),
+            None => k.clone(),
+        })
+        .collect();
+    assert_eq!(keys(&imperial), feet);
+    let dual = plan("39.624", "metric", &["--dual-units"]);
+    let dual_keys = keys(&dual);
+    for (i, key) in dual_keys.iter().enumerate() {
+        if let Some(stem) = key.strip_suffix("_ft") {
+            assert_eq!(dual_keys[i - 1], format!("{stem}_m"));
+        }
+    }
+    let without_feet: Vec<&String> = dual_keys.iter().filter(|k| !k.ends_with("_ft")).collect();
+    assert_eq!(without_feet, metric.iter().collect::<Vec<_>>());
+
+    // Warning depths print in the selected unit
+    let messages: Vec<&str> = imperial["warnings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|w| w["message"].as_str().unwrap())
+        .collect();
+    assert!(
+        messages.contains(&"segments[0] (130ft, 20 min): END 130ft exceeds 98.4ft"),
+        "{messages:?}"
+    );
+    assert!(
+        messages
+            .iter()
+            .all(|m| !m.contains("m,") && !m.contains("m)")),
+        "{messages:?}"
+    );
+}
+
+#[test]
+fn ndl_solvers_find_the_last_no_deco_segment() {
+    let plan = |input: &str, args: &[&str]| {
+        serde_json::from_slice::<Value>(&run(args, input).stdout).unwrap()
+    };
+    let solved = plan(&payload("30", "10"), &["--solve-bottom-time"]);
+    let minutes = solved["max_ndl_bottom_time_min"].as_u64().unwrap();
+    let at = |minutes: u64| plan(&payload("30", &minutes.to_string()), &[]);
+    assert_eq!(at(minutes)["deco_required"], false);
+    assert_eq!(at(minutes + 1)["deco_required"], true);
+
+    let solved = plan(&payload("30", "10"), &["--solve-max-depth"]);
+    let depth = solved["max_ndl_depth_m"].as_f64().unwrap();
+    let at = |depth: f64| plan(&payload(&depth.to_string(), "10"), &[]);
+    assert_eq!(at(depth)["deco_required"], false);
+    assert_eq!(at(depth + 0.1)["deco_required"], true);
+}
+
+#[test]
+fn last_stop_moves_the_shallowest_stop() {
+    let stops = |last_stop: &str| {
+        let input = payload("40", "30").replace(
+            r#""gf_high":0.7}"#,
+            &format!(r#""gf_high":0.7,"last_stop_m":{last_stop}}}"#),
+        );
+        let value: Value = serde_json::from_slice(&run(&[], &input).stdout).unwrap();
+        value["stops"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|stop| stop["depth_m"].as_f64().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(stops("3").last(), Some(&3.0));
+    let six = stops("6");
+    assert_eq!(six.last(), Some(&6.0));
+    assert!(six.iter().all(|&depth| depth >= 6.0), "{six:?}");
+}
+
+#[test]
+fn validate_only_checks_what_a_full_run_checks() {
+    // Air past its 1.4 bar MOD, and a hypoxic mix at the surface
+    let strict_inputs = [
+        (
+            r#"{"segments":[{"depth_m":60,"duration_min":10}],"gas":"air","gf_low":0.3,"gf_high":0.7,"descent_rate_m_per_min":18}"#,
+            28,
+        ),
+        (
+            r#"{"segments":[{"depth_m":0.5,"duration_min":10}],"gas":"tmx10/70","gf_low":0.3,"gf_high":0.7}"#,
+            28,
+        ),
+    ];
+    for (input, code) in strict_inputs {
+        for args in [&["--strict"][..], &["--strict", "--validate-only"]] {
+            assert_eq!(
+                run(args, input).status.code(),
+                Some(code),
+                "{args:?} {input}"
+            );
+        }
+    }
+
+    let input = r#"{"segments":[{"depth_m":33,"duration_min":8}],"gas":"ean32","gf_low":0.3,"gf_high":0.7,"descent_rate_m_per_min":18}"#;
+    let warnings = |args: &[&str]| {
+        let value: Value = serde_json::from_slice(&run(args, input).stdout).unwrap();
+        value["warnings"].clone()
+    };
+    let planned = warnings(&[]);
+    assert_eq!(planned.as_array().unwrap().len(), 3, "{planned}");
+    assert_eq!(warnings(&["--validate-only"]), planned);
+
+    // Warnings fail --strict in either mode
+    let checked = run(&["--strict", "--validate-only"], input);
+    assert_eq!(checked.status.code(), Some(28));
+    let value: Value = serde_json::from_slice(&checked.stdout).unwrap();
+    assert_eq!(value["valid"], false);
+    assert_eq!(run(&["--strict"], input).status.code(), Some(28));
+}
+
+#[test]
+fn deep_gas_switches_warn_at_the_switch_po2_limit() {
+    let deep_switches = |input: &str| {
+        let value: Value = serde_json::from_slice(&run(&[], input).stdout).unwrap();
+        value["warnings"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|warning| warning["code"] == "deep_gas_switch")
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    // max_deco_po2 1.8 lets the planner switch to EAN50 at 24m, past its
+    // 22m MOD at 1.6
+    let planned = r#"{"segments":[{"depth_m":50,"duration_min":25}],"gas":"tmx21/35","deco_gases":["ean50"],"max_deco_po2":1.8,"gf_low":0.3,"gf_high":0.7,"descent_rate_m_per_min":18}"#;
+    let warnings = deep_switches(planned);
+    assert_eq!(warnings.len(), 1, "{warnings:?}");
+    assert!(warnings[0]["message"]
+        .as_str()
+        .unwrap()
+        .contains("EAN50 at 24m"));
+    assert!(deep_switches(&planned.replace("1.8", "1.6")).is_empty());
+
+    // A switch to O2 at 9m written into the profile
+    let recorded = r#"{"segments":[{"depth_m":30,"duration_min":20},{"depth_m":9,"duration_min":5,"gas":"o2"}],"gas":"air","gf_low":0.3,"gf_high":0.7,"descent_rate_m_per_min":18}"#;
+    let warnings = deep_switches(recorded);
+    assert_eq!(warnings.len(), 1, "{warnings:?}");
+    assert_eq!(warnings[0]["segment_index"], 1);
+    assert!(deep_switches(&recorded.replace(r#""depth_m":9"#, r#""depth_m":6"#)).is_empty());
+}
+
+#[test]
+fn errors_and_warnings_index_segments_alike() {
+    // segments[1] fails on its duration, and warns on its MOD once valid
+    let input = |duration: &str| {
+        format!(
+            r#"{{"segments":[{{"depth_m":30,"duration_min":20}},{{"depth_m":60,"duration_min":{duration}}}],"gas":"air","gf_low":0.3,"gf_high":0.7}}"#
+        )
+    };
+    let failed = run(&[], &input("-1"));
+    assert_eq!(failed.status.code(), Some(22));
+    let error = String::from_utf8(failed.stderr).unwrap();
+    assert!(error.starts_with("segments[1]: "), "{error}");
+
+    let value: Value = serde_json::from_slice(&run(&[], &input("1")).stdout).unwrap();
+    let warning = value["warnings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|warning| warning["code"] == "mod_exceeded")
+        .unwrap()
+        .clone();
+    assert_eq!(warning["segment_index"], 1);
+    assert!(warning["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("segments[1] "));
+
+    // Prior dives and timestamps are indexed the same way
+    let timestamped = r#"{"dives":[{"segments":[{"depth_m":20,"timestamp_s":0},{"depth_m":20,"timestamp_s":0}],"surface_interval_min":60},{"segments":[{"depth_m":20,"duration_min":30}],"surface_interval_min":60}],"gas":"air","gf_low":0.3,"gf_high":0.7}"#;
+    let error = String::from_utf8(run(&[], timestamped).stderr).unwrap();
+    assert!(
+        error.starts_with("dives[0].segments[1]: timestamp_s must increase"),
+        "{error}"
+    );
+}
+
+#[test]
+fn csv_output_honours_precision_and_dual_units() {
+    let input = payload("40", "30");
+    let csv = |args: &[&str]| {
+        let mut args = args.to_vec();
+        args.extend(["--format", "csv"]);
+        let out = run(&args, &input);
+        assert_eq!(out.status.code(), Some(0), "{args:?}");
+        String::from_utf8(out.stdout).unwrap()
+    };
+
+    let rounded = csv(&["--precision", "0"]);
+    let rows: Vec<&str> = rounded.lines().skip(2).collect();
+    assert!(!rows.is_empty(), "{rounded}");
+    for row in &rows {
+        assert!(!row.contains('.'), "{rounded}");
+    }
+
+    let dual = csv(&["--dual-units", "--precision", "1"]);
+    let mut lines = dual.lines();
+    let summary = lines.next().unwrap();
+    assert!(summary.contains(",ceiling_ft="), "{summary}");
+    assert_eq!(lines.next(), Some("depth_m,depth_ft,duration_min"));
+    let first: Vec<f64> = lines
+        .next()
+        .unwrap()
+        .split(',')
+        .map(|v| v.parse().unwrap())
+        .collect();
+    assert_eq!(
+        first[1],
+        (first[0] / 0.3048 * 10.0).round() / 10.0,
+        "{dual}"
+    );
+}